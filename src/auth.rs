@@ -1,10 +1,14 @@
+use crate::config::AuthConfig;
+use crate::storage::Storage;
 use eyre::{bail, ensure, eyre, Result};
 use oauth2::basic::{BasicClient, BasicTokenResponse};
+use oauth2::devicecode::StandardDeviceAuthorizationResponse;
 use oauth2::reqwest::http_client;
 use oauth2::{
-    AuthType, AuthUrl, AuthorizationCode, ClientId, CsrfToken, PkceCodeChallenge, RedirectUrl,
-    Scope, TokenUrl,
+    AuthType, AuthUrl, AuthorizationCode, ClientId, CsrfToken, DeviceAuthorizationUrl,
+    PkceCodeChallenge, RedirectUrl, RefreshToken, Scope, TokenResponse, TokenUrl,
 };
+use serde_derive::{Deserialize, Serialize};
 use tiny_http::{Method, Request, Response, Server, StatusCode};
 use url::Url;
 
@@ -51,7 +55,7 @@ fn handle_request(request: Request, csrf_token: &CsrfToken) -> Result<String> {
             let base = Url::parse("http://localhost:3003/")?;
             let url = base.join(request.url())?;
             if url.path() == "/redirect" {
-                match extract_authorization_code(&url, &csrf_token) {
+                match extract_authorization_code(&url, csrf_token) {
                     Ok(code) => {
                         let response = Response::from_string("You may now close this window.");
                         if let Err(respond_err) = request.respond(response) {
@@ -96,24 +100,57 @@ fn start_server() -> eyre::Result<Server> {
     tiny_http::Server::http("127.0.0.1:0").map_err(|e| eyre!(e))
 }
 
-pub fn authenticate(client_id: String) -> Result<BasicTokenResponse> {
-    let ms_graph_authorize_url =
-        AuthUrl::new("https://login.microsoftonline.com/common/oauth2/v2.0/authorize".to_string())?;
-    let ms_graph_token_url = Some(TokenUrl::new(
-        "https://login.microsoftonline.com/common/oauth2/v2.0/token".to_string(),
-    )?);
+// The `offline_access` scope is required to receive a refresh token.
+const SCOPES: [&str; 2] = ["Files.Read.All", "offline_access"];
 
-    let server = start_server()?;
-    let redirect_url = format!("http://localhost:{}/redirect", server.server_addr().port());
+#[derive(Clone, Copy)]
+pub enum LoginMethod {
+    // Authorization code flow, using a local browser and a redirect to a local HTTP server
+    Browser,
+    // Device authorization grant, where the user signs in on any device with a browser
+    DeviceCode,
+}
 
+#[derive(Serialize, Deserialize)]
+pub struct SavedToken {
+    refresh_token: String,
+}
+
+fn ms_graph_client(config: &AuthConfig) -> Result<BasicClient> {
+    let ms_graph_authorize_url = AuthUrl::new(config.authorize_url.clone())?;
+    let ms_graph_token_url = Some(TokenUrl::new(config.token_url.clone())?);
+    let ms_graph_device_code_url = DeviceAuthorizationUrl::new(config.device_code_url.clone())?;
     let client = BasicClient::new(
-        ClientId::new(client_id),
+        ClientId::new(config.client_id.clone()),
         None,
         ms_graph_authorize_url,
         ms_graph_token_url,
     )
     .set_auth_type(AuthType::RequestBody)
-    .set_redirect_uri(RedirectUrl::new(redirect_url)?);
+    .set_device_authorization_url(ms_graph_device_code_url);
+    Ok(client)
+}
+
+fn refresh(client: &BasicClient, saved: SavedToken) -> Option<BasicTokenResponse> {
+    let refresh_token = RefreshToken::new(saved.refresh_token);
+    match client
+        .exchange_refresh_token(&refresh_token)
+        .add_scopes(SCOPES.iter().map(|s| Scope::new(s.to_string())))
+        .request(http_client)
+    {
+        Ok(token) => Some(token),
+        Err(err) => {
+            eprintln!("Error refreshing saved token: {}", err);
+            None
+        }
+    }
+}
+
+fn authorize_in_browser(client: BasicClient) -> Result<BasicTokenResponse> {
+    let server = start_server()?;
+    let redirect_url = format!("http://localhost:{}/redirect", server.server_addr().port());
+
+    let client = client.set_redirect_uri(RedirectUrl::new(redirect_url)?);
 
     // Setup PKCE code challenge
     let (pkce_challenge, pkce_verifier) = PkceCodeChallenge::new_random_sha256();
@@ -121,13 +158,16 @@ pub fn authenticate(client_id: String) -> Result<BasicTokenResponse> {
     // Generate the full authorization URL.
     let (auth_url, csrf_token) = client
         .authorize_url(CsrfToken::new_random)
-        .add_scope(Scope::new("Files.Read.All".to_string()))
+        .add_scopes(SCOPES.iter().map(|s| Scope::new(s.to_string())))
         .set_pkce_challenge(pkce_challenge)
         .url();
 
     if let Err(e) = open::that(auth_url.as_str()) {
-        println!("{}", e);
-        println!("Browse to {}", auth_url);
+        // Without a local browser the redirect cannot reach the server, so sign in on
+        // another device instead.
+        eprintln!("Unable to open browser: {}", e);
+        drop(server);
+        return authorize_with_device_code(&client);
     }
 
     let authorization_code = get_authorization_code(&server, csrf_token)?;
@@ -142,3 +182,52 @@ pub fn authenticate(client_id: String) -> Result<BasicTokenResponse> {
 
     Ok(token_result)
 }
+
+fn authorize_with_device_code(client: &BasicClient) -> Result<BasicTokenResponse> {
+    let details: StandardDeviceAuthorizationResponse = client
+        .exchange_device_code()?
+        .add_scopes(SCOPES.iter().map(|s| Scope::new(s.to_string())))
+        .request(http_client)?;
+
+    eprintln!(
+        "To sign in, browse to {} and enter the code {}",
+        details.verification_uri().as_str(),
+        details.user_code().secret()
+    );
+
+    let token_result = client.exchange_device_access_token(&details).request(
+        http_client,
+        std::thread::sleep,
+        None,
+    )?;
+
+    Ok(token_result)
+}
+
+// Use the saved refresh token to get an access token, falling back to interactive login if
+// there is no saved token or it is no longer valid.
+pub fn authenticate(
+    config: &AuthConfig,
+    login: LoginMethod,
+    token_cache: &Storage<SavedToken>,
+) -> Result<BasicTokenResponse> {
+    let client = ms_graph_client(config)?;
+    let token = match token_cache.load().and_then(|saved| refresh(&client, saved)) {
+        Some(token) => token,
+        None => match login {
+            LoginMethod::Browser => authorize_in_browser(client)?,
+            LoginMethod::DeviceCode => authorize_with_device_code(&client)?,
+        },
+    };
+    // The token endpoint may not return a new refresh token, in which case the saved one
+    // remains valid.
+    if let Some(refresh_token) = token.refresh_token() {
+        let saved = SavedToken {
+            refresh_token: refresh_token.secret().clone(),
+        };
+        if let Err(err) = token_cache.save(&saved) {
+            eprintln!("Error saving token: {}", err);
+        }
+    }
+    Ok(token)
+}
@@ -0,0 +1,363 @@
+use crate::cache::age_as_string;
+use crate::drive::Drive;
+use crate::filter::ItemFilter;
+use crate::folders::duplicate_folders;
+use crate::item::{DriveSnapshot, DriveState, Item, ItemType};
+use crate::keeper::Keeper;
+use crate::size::{bucket_by_size, percent, size_as_string};
+use eyre::Result;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Modifier, Style};
+use ratatui::text::{Line, Text};
+use ratatui::widgets::{List, ListItem, ListState};
+use ratatui::{DefaultTerminal, Frame};
+use std::collections::HashMap;
+use std::time::Duration;
+
+// Width of the bar showing the size of each entry relative to the largest in the folder
+const BAR_WIDTH: usize = 10;
+
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq)]
+struct Usage {
+    size: u64,
+    files: u64,
+    folders: u64,
+}
+
+// Add the usage of `folder` and each folder below it to `usage`
+fn folder_usage<'a>(
+    state: &'a DriveState,
+    folder: &'a Item,
+    usage: &mut HashMap<&'a str, Usage>,
+) -> Usage {
+    let mut total = Usage::default();
+    for child in state.children(folder) {
+        match child.item_type {
+            ItemType::File { .. } => {
+                total.size += child.size;
+                total.files += 1;
+            }
+            ItemType::Folder {} | ItemType::Package {} => {
+                let subfolder = folder_usage(state, child, usage);
+                total.size += subfolder.size;
+                total.files += subfolder.files;
+                total.folders += subfolder.folders + 1;
+            }
+        }
+    }
+    usage.insert(&folder.id, total);
+    total
+}
+
+struct Browser<'a> {
+    state: &'a DriveState,
+    usage: HashMap<&'a str, Usage>,
+    // marks the copy of each duplicate file or folder to keep with `+` and the copies to remove
+    // with `*`
+    duplicates: HashMap<&'a str, char>,
+    // bytes that percentages are relative to, the quota if it is known
+    total: u64,
+    title: String,
+    // the folders opened from the root, with the entry selected in each
+    path: Vec<(&'a Item, usize)>,
+    entries: Vec<&'a Item>,
+    list: ListState,
+}
+
+impl<'a> Browser<'a> {
+    fn new(
+        drive: &Drive,
+        snapshot: &'a DriveSnapshot,
+        filter: &ItemFilter,
+        keeper: &Keeper,
+        age: Option<Duration>,
+    ) -> Option<Self> {
+        let state = &snapshot.state;
+        let root = state.root()?;
+        let mut usage = HashMap::new();
+        let root_usage = folder_usage(state, root, &mut usage);
+        let mut buckets = bucket_by_size(state, filter, keeper);
+        let folders = duplicate_folders(state, &mut buckets, keeper);
+        let duplicates = buckets
+            .duplicates()
+            .map(|(_, _, files)| files)
+            .chain(folders.groups.iter().map(|group| group.folders.as_slice()))
+            .flat_map(|files| {
+                files
+                    .iter()
+                    .enumerate()
+                    .map(|(copy, file)| (file, if copy == 0 { '+' } else { '*' }))
+            })
+            .map(|(file, marker)| (file.item.id.as_str(), marker))
+            .collect();
+        let (total, usage_title) = match &drive.quota {
+            Some(quota) => (
+                quota.total,
+                format!(
+                    "{}  used {} of {} ({:.1}%)",
+                    drive.name,
+                    size_as_string(quota.used),
+                    size_as_string(quota.total),
+                    percent(quota.used, quota.total)
+                ),
+            ),
+            None => (
+                root_usage.size,
+                format!("{}  used {}", drive.name, size_as_string(root_usage.size)),
+            ),
+        };
+        let title = match age {
+            Some(_) => format!("{}  (cached {} ago)", usage_title, age_as_string(age)),
+            None => format!("{}  (cached)", usage_title),
+        };
+        let mut browser = Browser {
+            state,
+            usage,
+            duplicates,
+            total,
+            title,
+            path: Vec::new(),
+            entries: Vec::new(),
+            list: ListState::default(),
+        };
+        browser.open(root, 0);
+        Some(browser)
+    }
+
+    fn usage(&self, item: &Item) -> Usage {
+        entry_usage(item, &self.usage)
+    }
+
+    fn folder(&self) -> &'a Item {
+        self.path.last().expect("root folder is always open").0
+    }
+
+    fn open(&mut self, folder: &'a Item, selected: usize) {
+        self.path.push((folder, selected));
+        self.entries = sorted_entries(self.state, folder, &self.usage);
+        self.list
+            .select(Some(selected.min(self.entries.len().saturating_sub(1))));
+    }
+
+    fn open_selected(&mut self) {
+        let selected = self.list.selected().unwrap_or(0);
+        if let Some(&item) = self.entries.get(selected) {
+            if !matches!(item.item_type, ItemType::File { .. }) {
+                self.path.last_mut().expect("root folder is always open").1 = selected;
+                self.open(item, 0);
+            }
+        }
+    }
+
+    fn close(&mut self) {
+        if self.path.len() > 1 {
+            self.path.pop();
+            let (folder, selected) = self.path.pop().expect("root folder is always open");
+            self.open(folder, selected);
+        }
+    }
+
+    fn path_string(&self) -> String {
+        let names = self.path[1..]
+            .iter()
+            .map(|(folder, _)| folder.name.as_str())
+            .collect::<Vec<_>>();
+        format!("/{}", names.join("/"))
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [header, body, footer] = Layout::vertical([
+            Constraint::Length(2),
+            Constraint::Min(1),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+        frame.render_widget(
+            Text::from(vec![
+                Line::from(self.title.as_str()),
+                Line::styled(
+                    self.path_string(),
+                    Style::new().add_modifier(Modifier::BOLD),
+                ),
+            ]),
+            header,
+        );
+        let largest = self.entries.first().map_or(0, |item| self.usage(item).size);
+        let rows = self
+            .entries
+            .iter()
+            .map(|item| {
+                let usage = self.usage(item);
+                let filled = (usage.size * BAR_WIDTH as u64)
+                    .checked_div(largest)
+                    .unwrap_or(0) as usize;
+                let (name, count) = match item.item_type {
+                    ItemType::File { .. } => (item.name.clone(), String::new()),
+                    ItemType::Folder {} | ItemType::Package {} => {
+                        (format!("{}/", item.name), format!("{} files", usage.files))
+                    }
+                };
+                let marker = self
+                    .duplicates
+                    .get(item.id.as_str())
+                    .copied()
+                    .unwrap_or(' ');
+                ListItem::new(format!(
+                    "{:>14} {:>5.1}% [{:<bar$}] {:>12} {} {}",
+                    size_as_string(usage.size),
+                    percent(usage.size, self.total),
+                    "#".repeat(filled),
+                    count,
+                    marker,
+                    name,
+                    bar = BAR_WIDTH
+                ))
+            })
+            .collect::<Vec<_>>();
+        let list = List::new(rows).highlight_style(Style::new().add_modifier(Modifier::REVERSED));
+        frame.render_stateful_widget(list, body, &mut self.list);
+        let usage = self.usage(self.folder());
+        frame.render_widget(
+            Line::from(format!(
+                "{} in {} files and {} folders   + keep   * duplicate   Enter open   Backspace up   q quit",
+                size_as_string(usage.size),
+                usage.files,
+                usage.folders
+            )),
+            footer,
+        );
+    }
+
+    fn run(&mut self, terminal: &mut DefaultTerminal) -> Result<()> {
+        loop {
+            terminal.draw(|frame| self.draw(frame))?;
+            let key = match event::read()? {
+                Event::Key(key) if key.kind == KeyEventKind::Press => key,
+                _ => continue,
+            };
+            let last = self.entries.len().saturating_sub(1);
+            let selected = self.list.selected().unwrap_or(0);
+            match key.code {
+                KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+                KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                    return Ok(())
+                }
+                KeyCode::Up | KeyCode::Char('k') => self.list.select_previous(),
+                KeyCode::Down | KeyCode::Char('j') => self.list.select_next(),
+                KeyCode::PageUp => self.list.select(Some(selected.saturating_sub(20))),
+                KeyCode::PageDown => self.list.select(Some((selected + 20).min(last))),
+                KeyCode::Home => self.list.select_first(),
+                KeyCode::End => self.list.select(Some(last)),
+                KeyCode::Enter | KeyCode::Right | KeyCode::Char('l') => self.open_selected(),
+                KeyCode::Backspace | KeyCode::Left | KeyCode::Char('h') => self.close(),
+                _ => {}
+            }
+        }
+    }
+}
+
+// Usage of a file, or of a folder and its subfolders
+fn entry_usage(item: &Item, usage: &HashMap<&str, Usage>) -> Usage {
+    match item.item_type {
+        ItemType::File { .. } => Usage {
+            size: item.size,
+            files: 1,
+            folders: 0,
+        },
+        ItemType::Folder {} | ItemType::Package {} => {
+            usage.get(item.id.as_str()).copied().unwrap_or_default()
+        }
+    }
+}
+
+// Files and subfolders of a folder, largest first
+fn sorted_entries<'a>(
+    state: &'a DriveState,
+    folder: &Item,
+    usage: &HashMap<&str, Usage>,
+) -> Vec<&'a Item> {
+    let mut entries = state.children(folder).collect::<Vec<_>>();
+    entries
+        .sort_by_cached_key(|item| (std::cmp::Reverse(entry_usage(item, usage).size), &item.name));
+    entries
+}
+
+// Browse the cached folders of a drive in the terminal, until the user quits
+pub(crate) fn browse(
+    drive: &Drive,
+    snapshot: &DriveSnapshot,
+    filter: &ItemFilter,
+    keeper: &Keeper,
+    age: Option<Duration>,
+) -> Result<()> {
+    let mut browser = match Browser::new(drive, snapshot, filter, keeper, age) {
+        Some(browser) => browser,
+        None => {
+            eprintln!("No cached folders for drive {}", drive.id);
+            return Ok(());
+        }
+    };
+    let mut terminal = ratatui::try_init()?;
+    let result = browser.run(&mut terminal);
+    ratatui::try_restore()?;
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{folder_usage, sorted_entries, Usage};
+    use crate::item::DriveState;
+    use serde_json::json;
+    use std::collections::HashMap;
+
+    #[test]
+    fn folders_by_size() {
+        let mut state = DriveState::default();
+        let item = |id: &str, parent: &str, size: Option<u64>| {
+            let mut data = json!({
+                "id": id,
+                "name": id,
+                "parentReference": {"id": parent, "driveType": "personal"},
+            });
+            match size {
+                Some(size) => {
+                    data["size"] = json!(size);
+                    data["file"] = json!({});
+                }
+                None => data["folder"] = json!({}),
+            }
+            data
+        };
+        let mut root = json!({"id": "ROOT", "name": "root", "folder": {}, "root": {}});
+        root["parentReference"] = json!({"driveType": "personal"});
+        for data in [
+            root,
+            item("A", "ROOT", None),
+            item("B", "A", None),
+            item("a", "A", Some(100)),
+            item("b", "B", Some(300)),
+            item("r", "ROOT", Some(200)),
+            item("s", "ROOT", Some(200)),
+        ] {
+            state.upsert(serde_json::from_value(data).unwrap());
+        }
+        let mut usage = HashMap::new();
+        let root = state.root().unwrap();
+        let total = folder_usage(&state, root, &mut usage);
+        assert_eq!(
+            total,
+            Usage {
+                size: 800,
+                files: 4,
+                folders: 2
+            }
+        );
+        let names = sorted_entries(&state, root, &usage)
+            .into_iter()
+            .map(|item| item.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["A", "r", "s"]);
+        assert_eq!(usage["A"].files, 2);
+    }
+}
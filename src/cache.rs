@@ -0,0 +1,184 @@
+use eyre::Result;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+// Increment the number after `drive` when the serialized format changes.
+// 2021-05-23 - updated to 2 because the original delta link format is no longer valid
+// 2021-06-05 - remove mime type from saved data
+// 2026-10-16 - store parent IDs instead of parent paths
+// 2026-10-16 - store timestamps and authors, which a delta sync does not add to unchanged items
+const DRIVE_PREFIX: &str = "drive5_";
+// The drive details, saved separately so that cached drives can be selected without loading them
+const INFO_PREFIX: &str = "info_";
+const TOKEN_PREFIX: &str = "token_";
+const EXTENSION: &str = "cbor";
+
+pub struct CachedDrive {
+    pub drive_id: String,
+    pub path: PathBuf,
+    pub info_path: PathBuf,
+    pub size: u64,
+    pub modified: Option<SystemTime>,
+}
+
+impl CachedDrive {
+    pub fn age(&self) -> Option<Duration> {
+        self.modified?.elapsed().ok()
+    }
+}
+
+pub fn age_as_string(age: Option<Duration>) -> String {
+    match age.map(|age| age.as_secs()) {
+        None => "unknown age".to_owned(),
+        Some(secs) if secs < 60 * 60 => format!("{} minutes", secs / 60),
+        Some(secs) if secs < 48 * 60 * 60 => format!("{} hours", secs / 60 / 60),
+        Some(secs) => format!("{} days", secs / 60 / 60 / 24),
+    }
+}
+
+pub fn cache_dir(
+    cache_dir: Option<PathBuf>,
+    project: &Option<directories::ProjectDirs>,
+) -> Option<PathBuf> {
+    let cache_path = cache_dir.or_else(|| {
+        project
+            .as_ref()
+            .map(|project| project.cache_dir().to_path_buf())
+    })?;
+    if std::fs::create_dir_all(&cache_path).is_err() {
+        // let a later error sort it out
+    }
+    Some(cache_path)
+}
+
+fn filename(cache_dir: &Path, prefix: &str, id: &str) -> PathBuf {
+    let mut path = cache_dir.to_path_buf();
+    // append the extension, as a tenant name may contain dots
+    path.push(format!("{}{}.{}", prefix, id, EXTENSION));
+    path
+}
+
+pub fn cache_filename(cache_dir: &Path, drive_id: &str) -> PathBuf {
+    filename(cache_dir, DRIVE_PREFIX, drive_id)
+}
+
+pub fn info_filename(cache_dir: &Path, drive_id: &str) -> PathBuf {
+    filename(cache_dir, INFO_PREFIX, drive_id)
+}
+
+// A token is only valid for the client ID and tenant that it was issued for
+pub fn token_filename(cache_dir: &Path, client_id: &str, tenant: &str) -> PathBuf {
+    // Storage writes through a `NamedTempFile`, which is created readable only by the user.
+    filename(
+        cache_dir,
+        TOKEN_PREFIX,
+        &format!("{}_{}", client_id, tenant),
+    )
+}
+
+fn cached_ids(cache_dir: &Path, prefix: &str) -> Result<Vec<(String, PathBuf)>> {
+    let mut ids = Vec::new();
+    for entry in std::fs::read_dir(cache_dir)? {
+        let path = entry?.path();
+        if path.extension().and_then(|ext| ext.to_str()) != Some(EXTENSION) {
+            continue;
+        }
+        let id = match path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .and_then(|stem| stem.strip_prefix(prefix))
+        {
+            Some(id) => id.to_owned(),
+            None => continue,
+        };
+        ids.push((id, path));
+    }
+    ids.sort();
+    Ok(ids)
+}
+
+pub fn cached_drives(cache_dir: &Path) -> Result<Vec<CachedDrive>> {
+    let mut drives = Vec::new();
+    for (drive_id, path) in cached_ids(cache_dir, DRIVE_PREFIX)? {
+        let metadata = std::fs::metadata(&path)?;
+        drives.push(CachedDrive {
+            info_path: info_filename(cache_dir, &drive_id),
+            drive_id,
+            path,
+            size: metadata.len(),
+            modified: metadata.modified().ok(),
+        });
+    }
+    Ok(drives)
+}
+
+// Drive data saved with an earlier `DRIVE_PREFIX`, which is never read again
+pub fn stale_drives(cache_dir: &Path) -> Result<Vec<(String, PathBuf)>> {
+    let mut drives = Vec::new();
+    for entry in std::fs::read_dir(cache_dir)? {
+        let path = entry?.path();
+        let name = match path.file_name().and_then(|name| name.to_str()) {
+            Some(name) if !name.starts_with(DRIVE_PREFIX) => name,
+            _ => continue,
+        };
+        // the first versions did not add the extension
+        let stem = name
+            .strip_suffix(&format!(".{}", EXTENSION))
+            .unwrap_or(name);
+        let id = match stem
+            .strip_prefix("drive")
+            .map(|rest| rest.trim_start_matches(|c: char| c.is_ascii_digit()))
+            .and_then(|rest| rest.strip_prefix('_'))
+        {
+            Some(id) if !id.is_empty() => id.to_owned(),
+            _ => continue,
+        };
+        drives.push((id, path));
+    }
+    drives.sort();
+    Ok(drives)
+}
+
+pub fn cached_tokens(cache_dir: &Path) -> Result<Vec<PathBuf>> {
+    Ok(cached_ids(cache_dir, TOKEN_PREFIX)?
+        .into_iter()
+        .map(|(_, path)| path)
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{cache_filename, info_filename, stale_drives};
+
+    #[test]
+    fn earlier_drive_prefixes() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in &[
+            "drive3_A",
+            "drive3_B.cbor",
+            "drive4_A.cbor",
+            "token_C_common.cbor",
+        ] {
+            std::fs::write(dir.path().join(name), b"").unwrap();
+        }
+        std::fs::write(cache_filename(dir.path(), "A"), b"").unwrap();
+        std::fs::write(info_filename(dir.path(), "A"), b"").unwrap();
+        let stale: Vec<(String, String)> = stale_drives(dir.path())
+            .unwrap()
+            .into_iter()
+            .map(|(id, path)| (id, path.file_name().unwrap().to_str().unwrap().to_owned()))
+            .collect();
+        let expected = [
+            ("A", "drive3_A"),
+            ("A", "drive4_A.cbor"),
+            ("B", "drive3_B.cbor"),
+        ];
+        assert_eq!(
+            stale,
+            expected
+                .iter()
+                .map(|(id, name)| (id.to_string(), name.to_string()))
+                .collect::<Vec<_>>()
+        );
+    }
+}
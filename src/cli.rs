@@ -0,0 +1,232 @@
+use crate::size::parse_size;
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use std::path::PathBuf;
+
+/// Display useful information about OneDrive, including total disk usage and duplicate files.
+///
+/// With no command, show the usage and duplicates for each drive.
+#[derive(Parser)]
+#[command(version)]
+pub struct Cli {
+    /// Read settings from this file, instead of `config.toml` in the user config directory.
+    #[arg(long, value_name = "FILE", env = "MSOD_STAT_CONFIG", global = true)]
+    pub config: Option<PathBuf>,
+
+    /// Only process the drive with this ID or name. May be repeated.
+    #[arg(long = "drive", value_name = "ID|NAME", global = true)]
+    pub drives: Vec<String>,
+
+    /// Only process drives of this type. May be repeated.
+    #[arg(
+        long = "drive-type",
+        value_name = "TYPE",
+        value_parser = ["personal", "business", "documentLibrary"],
+        global = true
+    )]
+    pub drive_types: Vec<String>,
+
+    /// Directory for cached drive data, instead of the user cache directory.
+    #[arg(long, value_name = "DIR", global = true)]
+    pub cache_dir: Option<PathBuf>,
+
+    /// Report from the cached drive data, without signing in or updating the cache.
+    #[arg(long, global = true)]
+    pub offline: bool,
+
+    /// Microsoft Graph API endpoint [default: https://graph.microsoft.com/v1.0]
+    #[arg(long, value_name = "URL", env = "MSOD_STAT_GRAPH_URL", global = true)]
+    pub graph_url: Option<String>,
+
+    /// Output format.
+    #[arg(long, value_enum, default_value_t = Format::Text, global = true)]
+    pub format: Format,
+
+    #[command(flatten, next_help_heading = "Sign-in options")]
+    pub sign_in: SignIn,
+
+    #[command(flatten, next_help_heading = "Duplicate options")]
+    pub filter: FilterArgs,
+
+    #[command(flatten, next_help_heading = "Duplicate options")]
+    pub keeper: KeeperArgs,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Args)]
+pub struct SignIn {
+    /// Sign in by entering a code in a browser on another device.
+    #[arg(long, global = true)]
+    pub device_code: bool,
+
+    /// Application (client) ID of the Azure AD app registration.
+    #[arg(long, value_name = "ID", env = "MSOD_STAT_CLIENT_ID", global = true)]
+    pub client_id: Option<String>,
+
+    /// Azure AD tenant used to sign in [default: common]
+    #[arg(long, env = "MSOD_STAT_TENANT", global = true)]
+    pub tenant: Option<String>,
+
+    /// OAuth2 authorization endpoint, instead of the one for the tenant.
+    #[arg(
+        long,
+        value_name = "URL",
+        env = "MSOD_STAT_AUTHORIZE_URL",
+        global = true
+    )]
+    pub authorize_url: Option<String>,
+
+    /// OAuth2 token endpoint, instead of the one for the tenant.
+    #[arg(long, value_name = "URL", env = "MSOD_STAT_TOKEN_URL", global = true)]
+    pub token_url: Option<String>,
+
+    /// OAuth2 device authorization endpoint, instead of the one for the tenant.
+    #[arg(
+        long,
+        value_name = "URL",
+        env = "MSOD_STAT_DEVICE_CODE_URL",
+        global = true
+    )]
+    pub device_code_url: Option<String>,
+}
+
+#[derive(Args)]
+pub struct FilterArgs {
+    /// Ignore files smaller than this size, e.g. 100K, 5M or 1G.
+    #[arg(long, value_name = "SIZE", value_parser = parse_size, global = true)]
+    pub min_size: Option<u64>,
+
+    /// Only include files with a path matching this glob, e.g. "Pictures/**". May be repeated.
+    #[arg(long, value_name = "GLOB", global = true)]
+    pub include: Vec<String>,
+
+    /// Ignore files with a path matching this glob, e.g. "**/desktop.ini". May be repeated.
+    #[arg(long, value_name = "GLOB", global = true)]
+    pub exclude: Vec<String>,
+
+    /// Only include files with this extension, e.g. jpg. May be repeated.
+    #[arg(long = "extension", value_name = "EXT", global = true)]
+    pub extensions: Vec<String>,
+
+    /// Ignore paths matching this gitignore-style rule, after the rules in the `ignore` file in
+    /// the user config directory. May be repeated.
+    #[arg(long = "ignore", value_name = "RULE", global = true)]
+    pub ignore_rules: Vec<String>,
+
+    /// Also show files without a hash, such as OneNote files, that have the same size and name
+    /// as probable duplicates. These are listed separately, as their contents may differ.
+    #[arg(long, global = true)]
+    pub probable: bool,
+}
+
+#[derive(Args)]
+pub struct KeeperArgs {
+    /// How to choose the copy of a duplicate file to keep, tried in order until one copy is
+    /// preferred. May be repeated. By default, the first copy by path is kept.
+    #[arg(long = "keep", value_name = "POLICY", value_enum, global = true)]
+    pub policies: Vec<KeepPolicy>,
+
+    /// Keep copies in this folder, e.g. "Pictures/Sorted". May be repeated, with the most
+    /// preferred folder first.
+    #[arg(long = "prefer-folder", value_name = "PATH", global = true)]
+    pub preferred_folders: Vec<String>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum KeepPolicy {
+    /// The copy with the shortest path
+    ShortestPath,
+    /// The copy created first
+    Oldest,
+    /// The copy modified last
+    Newest,
+    /// The copy in the first of the `--prefer-folder` folders
+    PreferredFolder,
+    /// A copy outside any Camera Roll folder
+    NotCameraRoll,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    /// Human-readable text
+    Text,
+    /// JSON document, for use by other programs
+    Json,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// List the drives with their IDs, names, owners and types.
+    ListDrives,
+    /// Show the storage quota for each drive.
+    Usage,
+    /// Update the cached drive data.
+    Sync,
+    /// Show files with identical contents.
+    Duplicates,
+    /// Show the largest files, and the largest folders including their subfolders.
+    Largest {
+        /// Number of files and of folders to show.
+        #[arg(short = 'n', long, default_value_t = 20)]
+        count: usize,
+        /// Only show files and folders at least this many levels below the drive root.
+        #[arg(long, value_name = "DEPTH", default_value_t = 1)]
+        min_depth: usize,
+        /// Only show files and folders at most this many levels below the drive root.
+        #[arg(long, value_name = "DEPTH")]
+        max_depth: Option<usize>,
+    },
+    /// Show the space used by each folder.
+    Tree {
+        /// Number of folder levels to show.
+        #[arg(short, long, default_value_t = 2)]
+        depth: usize,
+    },
+    /// Browse the space used by each folder in the terminal, marking duplicates. Uses the cached
+    /// drive data, so run `sync` first to update it. With several drives, quitting opens the next
+    /// drive.
+    Browse,
+    /// Compare a local folder with the drive, showing the files found only locally, only in the
+    /// drive, or in both.
+    Compare {
+        /// Local folder to compare.
+        #[arg(value_name = "DIR")]
+        local: PathBuf,
+        /// Drive folder to compare with, instead of the whole drive, e.g. "Pictures/2015".
+        #[arg(long, value_name = "PATH", default_value = "")]
+        drive_folder: String,
+    },
+    /// Write every file and folder as CSV, with its path, type, size and hashes.
+    Inventory {
+        /// Write to this file instead of standard output.
+        #[arg(short, long, value_name = "FILE")]
+        output: Option<PathBuf>,
+    },
+    /// Write a self-contained HTML report with usage, a folder treemap and duplicates.
+    Html {
+        /// File to write the report to.
+        #[arg(
+            short,
+            long,
+            value_name = "FILE",
+            default_value = "onedrive-report.html"
+        )]
+        output: PathBuf,
+    },
+    /// Manage the cached drive data.
+    Cache {
+        #[command(subcommand)]
+        action: CacheAction,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum CacheAction {
+    /// Show the cache directory.
+    Path,
+    /// List the drives with cached data.
+    List,
+    /// Remove cached drive data and the saved sign-in token.
+    Clear,
+}
@@ -0,0 +1,323 @@
+use crate::filter::ItemFilter;
+use crate::item::{DriveState, ItemType};
+use crate::quickxor::QuickXorHash;
+use crate::size::{item_hashes, join_path, FileRef, ItemHash};
+use eyre::Result;
+use sha1::{Digest, Sha1};
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+pub(crate) struct LocalFile {
+    // relative to the local folder, with `/` separators
+    pub path: String,
+    pub size: u64,
+}
+
+pub(crate) struct Comparison<'a> {
+    // each local file found in the drive, with its copies in the drive
+    pub both: Vec<(LocalFile, Vec<FileRef<'a>>)>,
+    pub local_only: Vec<LocalFile>,
+    // drive files not found locally. Files without a hash are not compared.
+    pub drive_only: Vec<FileRef<'a>>,
+}
+
+// Hashes of a local file, in the forms used by Graph
+fn hash_file(
+    path: &Path,
+    sha1: bool,
+    quick_xor: bool,
+    bar: &indicatif::ProgressBar,
+) -> Result<Vec<ItemHash>> {
+    let mut file = File::open(path)?;
+    let mut sha1_hasher = Sha1::new();
+    let mut xor_hasher = QuickXorHash::new();
+    let mut buffer = vec![0; 1 << 20];
+    loop {
+        let count = file.read(&mut buffer)?;
+        if count == 0 {
+            break;
+        }
+        if sha1 {
+            sha1_hasher.update(&buffer[..count]);
+        }
+        if quick_xor {
+            xor_hasher.update(&buffer[..count]);
+        }
+        bar.inc(count as u64);
+    }
+    let mut hashes = Vec::new();
+    if sha1 {
+        let digest = sha1_hasher.finalize();
+        let hex = digest.iter().map(|byte| format!("{:02X}", byte)).collect();
+        hashes.push(ItemHash::Sha1(hex));
+    }
+    if quick_xor {
+        hashes.push(ItemHash::QuickXor(xor_hasher.finish()));
+    }
+    Ok(hashes)
+}
+
+// Files below a local folder, sorted by path
+fn local_files(local: &Path, filter: &ItemFilter) -> Vec<LocalFile> {
+    let mut files = Vec::new();
+    for entry in walkdir::WalkDir::new(local).sort_by_file_name() {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(error) => {
+                eprintln!("Ignoring unreadable local path: {}", error);
+                continue;
+            }
+        };
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        let size = match entry.metadata() {
+            Ok(metadata) => metadata.len(),
+            Err(error) => {
+                eprintln!("Ignoring unreadable local path: {}", error);
+                continue;
+            }
+        };
+        let path = entry
+            .path()
+            .strip_prefix(local)
+            .unwrap_or_else(|_| entry.path())
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        if filter.matches(&path, size) {
+            files.push(LocalFile { path, size });
+        }
+    }
+    files
+}
+
+// Match the files below a local folder with the files below `drive_folder` in the drive by
+// their contents. Local files are only hashed if a drive file has the same size, and only with
+// the hash types that the drive files have.
+pub(crate) fn compare<'a>(
+    state: &'a DriveState,
+    drive_folder: &str,
+    local: &Path,
+    filter: &ItemFilter,
+) -> Result<Comparison<'a>> {
+    if !local.is_dir() {
+        eyre::bail!("{} is not a folder", local.display());
+    }
+    let drive_folder = drive_folder.trim_matches('/');
+    let prefix = format!("{}/", drive_folder);
+    let mut drive_files = HashMap::<u64, HashMap<ItemHash, Vec<FileRef>>>::new();
+    let (mut sha1, mut quick_xor) = (false, false);
+    for item in state.items.values() {
+        if !matches!(item.item_type, ItemType::File { .. }) {
+            continue;
+        }
+        let path = match state.parent_path(item) {
+            Some(dirname) => join_path(&dirname, &item.name),
+            None => continue,
+        };
+        // filter by the path below the drive folder, as local files are filtered by the path
+        // below the local folder
+        let relative = if drive_folder.is_empty() {
+            Some(path.as_str())
+        } else {
+            path.strip_prefix(&prefix)
+        };
+        if !relative.is_some_and(|relative| filter.matches(relative, item.size)) {
+            continue;
+        }
+        // index the file under each of its hashes
+        for hash in item_hashes(item) {
+            match hash {
+                ItemHash::Sha1(_) => sha1 = true,
+                ItemHash::QuickXor(_) => quick_xor = true,
+            }
+            drive_files
+                .entry(item.size)
+                .or_default()
+                .entry(hash)
+                .or_default()
+                .push(FileRef {
+                    item,
+                    path: path.clone(),
+                });
+        }
+    }
+
+    let local_files = local_files(local, filter);
+    let bar = indicatif::ProgressBar::new(
+        local_files
+            .iter()
+            .filter(|file| drive_files.contains_key(&file.size))
+            .map(|file| file.size)
+            .sum(),
+    );
+    bar.set_style(
+        indicatif::ProgressStyle::default_bar()
+            .template("Hashing local files: [{elapsed_precise}] {wide_bar} {percent}%")
+            .progress_chars("#>-"),
+    );
+    bar.tick();
+    let mut both = Vec::new();
+    let mut local_only = Vec::new();
+    let mut found = HashSet::new();
+    for file in local_files {
+        let files_by_hash = match drive_files.get(&file.size) {
+            Some(files_by_hash) => files_by_hash,
+            None => {
+                local_only.push(file);
+                continue;
+            }
+        };
+        let hashes = match hash_file(&local.join(&file.path), sha1, quick_xor, &bar) {
+            Ok(hashes) => hashes,
+            Err(error) => {
+                bar.println(format!(
+                    "Ignoring unreadable local file {}: {}",
+                    file.path, error
+                ));
+                continue;
+            }
+        };
+        let mut copies = Vec::new();
+        for copy in hashes
+            .iter()
+            .filter_map(|hash| files_by_hash.get(hash))
+            .flatten()
+        {
+            // a drive file with both hashes is indexed under each of them
+            if copies
+                .iter()
+                .any(|seen: &FileRef| seen.item.id == copy.item.id)
+            {
+                continue;
+            }
+            found.insert(copy.item.id.as_str());
+            copies.push(FileRef {
+                item: copy.item,
+                path: copy.path.clone(),
+            });
+        }
+        if copies.is_empty() {
+            local_only.push(file);
+        } else {
+            both.push((file, copies));
+        }
+    }
+    bar.finish_and_clear();
+    let mut drive_only = drive_files
+        .into_values()
+        .flat_map(HashMap::into_values)
+        .flatten()
+        .filter(|file| found.insert(file.item.id.as_str()))
+        .collect::<Vec<_>>();
+    drive_only.sort_unstable_by(|a, b| a.path.cmp(&b.path));
+    Ok(Comparison {
+        both,
+        local_only,
+        drive_only,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::compare;
+    use crate::cli::FilterArgs;
+    use crate::filter::ItemFilter;
+    use crate::item::DriveState;
+    use serde_json::json;
+    use std::fs;
+
+    #[test]
+    fn compare_local_folder() {
+        let local = tempfile::tempdir().unwrap();
+        fs::create_dir(local.path().join("sub")).unwrap();
+        fs::write(local.path().join("sub/hello.txt"), "hello world").unwrap();
+        fs::write(local.path().join("other.txt"), "hello there").unwrap();
+        fs::write(local.path().join("new.txt"), "only local").unwrap();
+        let mut state = DriveState::default();
+        let item = |id: &str, parent: Option<&str>, file: Option<(u64, &str)>| {
+            let mut data = json!({
+                "id": id,
+                "name": id,
+                "parentReference": {"id": parent, "driveType": "business"},
+            });
+            match file {
+                Some((size, xor)) => {
+                    data["size"] = json!(size);
+                    data["file"] = json!({"hashes": {"quickXorHash": xor}});
+                }
+                None => data["folder"] = json!({}),
+            }
+            if parent.is_none() {
+                data["root"] = json!({});
+            }
+            data
+        };
+        for data in [
+            item("ROOT", None, None),
+            item("Backup", Some("ROOT"), None),
+            item(
+                "hello.txt",
+                Some("Backup"),
+                Some((11, "aCgDG9jwBhDc4Q1yawMZAAAAAAA=")),
+            ),
+            // same size as other.txt, but different contents
+            item(
+                "changed.txt",
+                Some("Backup"),
+                Some((11, "AAAAAAAAAAAAAAAAAAAAAAAAAAA=")),
+            ),
+            item(
+                "copy.txt",
+                Some("ROOT"),
+                Some((11, "aCgDG9jwBhDc4Q1yawMZAAAAAAA=")),
+            ),
+        ] {
+            state.upsert(serde_json::from_value(data).unwrap());
+        }
+        let filter = ItemFilter::default();
+        let comparison = compare(&state, "/Backup/", local.path(), &filter).unwrap();
+        let both = comparison
+            .both
+            .iter()
+            .map(|(file, copies)| (file.path.as_str(), copies.len()))
+            .collect::<Vec<_>>();
+        assert_eq!(both, [("sub/hello.txt", 1)]);
+        let local_only = comparison
+            .local_only
+            .iter()
+            .map(|file| file.path.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(local_only, ["new.txt", "other.txt"]);
+        let drive_only = comparison
+            .drive_only
+            .iter()
+            .map(|file| file.path.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(drive_only, ["Backup/changed.txt"]);
+        // comparing with the whole drive finds both copies
+        let comparison = compare(&state, "", local.path(), &filter).unwrap();
+        assert_eq!(comparison.both[0].1.len(), 2);
+        // globs match the paths below the compared folders on both sides
+        let filter = ItemFilter::new(
+            &FilterArgs {
+                min_size: None,
+                include: vec!["*.txt".to_owned()],
+                exclude: Vec::new(),
+                extensions: Vec::new(),
+                ignore_rules: Vec::new(),
+                probable: false,
+            },
+            None,
+        )
+        .unwrap();
+        let comparison = compare(&state, "Backup", &local.path().join("sub"), &filter).unwrap();
+        assert_eq!(comparison.both[0].0.path, "hello.txt");
+        assert!(comparison.local_only.is_empty());
+        assert_eq!(comparison.drive_only.len(), 1);
+    }
+}
@@ -0,0 +1,199 @@
+use crate::cli::SignIn;
+use eyre::{Result, WrapErr};
+use serde_derive::Deserialize;
+use std::path::{Path, PathBuf};
+
+// To replace this client ID, register a Public client/native application in Azure Active Directory.
+// See https://docs.microsoft.com/azure/active-directory/develop/quickstart-register-app
+// Under `Authentication` set the redirect URI to `http://localhost/redirect` and enable `Allow public client flows`.
+// Under `API permissions` add Microsoft Graph delegated permission `Files.Read.All`.
+// Set the `Application (client) ID` as `client-id` in the config file, or replace the default below.
+const DEFAULT_CLIENT_ID: &str = "3a139972-0147-433a-9ab8-faa3dd1b9eb5";
+const DEFAULT_TENANT: &str = "common";
+const DEFAULT_GRAPH_URL: &str = "https://graph.microsoft.com/v1.0";
+
+// Settings read from `config.toml` in the project config directory, e.g.
+//
+// client-id = "00000000-0000-0000-0000-000000000000"
+// tenant = "contoso.onmicrosoft.com"
+#[derive(Default, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct ConfigFile {
+    client_id: Option<String>,
+    tenant: Option<String>,
+    authorize_url: Option<String>,
+    token_url: Option<String>,
+    device_code_url: Option<String>,
+    graph_url: Option<String>,
+}
+
+impl ConfigFile {
+    pub fn load(path: &Path) -> Result<ConfigFile> {
+        match std::fs::read_to_string(path) {
+            Ok(text) => toml::from_str(&text)
+                .wrap_err_with(|| format!("Invalid config file {}", path.display())),
+            // a missing config file is the same as an empty one
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(ConfigFile::default()),
+            Err(err) => {
+                Err(err).wrap_err_with(|| format!("Cannot read config file {}", path.display()))
+            }
+        }
+    }
+}
+
+pub struct Config {
+    pub auth: AuthConfig,
+    // Microsoft Graph API endpoint, without a trailing slash
+    pub graph_url: String,
+}
+
+impl Config {
+    pub fn new(graph_url: Option<String>, sign_in: SignIn, mut file: ConfigFile) -> Config {
+        let graph_url = graph_url
+            .or_else(|| file.graph_url.take())
+            .unwrap_or_else(|| DEFAULT_GRAPH_URL.to_owned());
+        Config {
+            auth: AuthConfig::new(sign_in, file),
+            graph_url: graph_url.trim_end_matches('/').to_owned(),
+        }
+    }
+}
+
+pub struct AuthConfig {
+    pub client_id: String,
+    pub tenant: String,
+    pub authorize_url: String,
+    pub token_url: String,
+    pub device_code_url: String,
+}
+
+impl AuthConfig {
+    // Command-line flags and environment variables (already merged by clap) take precedence
+    // over the config file, which takes precedence over the defaults.
+    fn new(cli: SignIn, file: ConfigFile) -> AuthConfig {
+        let tenant = cli
+            .tenant
+            .or(file.tenant)
+            .unwrap_or_else(|| DEFAULT_TENANT.to_owned());
+        let endpoint = |name: &str| {
+            format!(
+                "https://login.microsoftonline.com/{}/oauth2/v2.0/{}",
+                tenant, name
+            )
+        };
+        AuthConfig {
+            client_id: cli
+                .client_id
+                .or(file.client_id)
+                .unwrap_or_else(|| DEFAULT_CLIENT_ID.to_owned()),
+            authorize_url: cli
+                .authorize_url
+                .or(file.authorize_url)
+                .unwrap_or_else(|| endpoint("authorize")),
+            token_url: cli
+                .token_url
+                .or(file.token_url)
+                .unwrap_or_else(|| endpoint("token")),
+            device_code_url: cli
+                .device_code_url
+                .or(file.device_code_url)
+                .unwrap_or_else(|| endpoint("devicecode")),
+            tenant,
+        }
+    }
+}
+
+pub fn config_filename(
+    config_file: Option<PathBuf>,
+    project: &Option<directories::ProjectDirs>,
+) -> Option<PathBuf> {
+    config_file.or_else(|| {
+        project
+            .as_ref()
+            .map(|project| project.config_dir().join("config.toml"))
+    })
+}
+
+// Gitignore-style rules for paths to leave out of duplicate analysis, next to the config file
+pub fn ignore_filename(
+    config_file: Option<&Path>,
+    project: &Option<directories::ProjectDirs>,
+) -> Option<PathBuf> {
+    match config_file {
+        Some(config_file) => Some(config_file.with_file_name("ignore")),
+        None => project
+            .as_ref()
+            .map(|project| project.config_dir().join("ignore")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Config, ConfigFile};
+    use crate::cli::SignIn;
+
+    fn sign_in(client_id: Option<&str>, tenant: Option<&str>) -> SignIn {
+        SignIn {
+            device_code: false,
+            client_id: client_id.map(str::to_owned),
+            tenant: tenant.map(str::to_owned),
+            authorize_url: None,
+            token_url: None,
+            device_code_url: None,
+        }
+    }
+
+    #[test]
+    fn default_endpoints() {
+        let config = Config::new(None, sign_in(None, None), ConfigFile::default());
+        assert_eq!(config.graph_url, "https://graph.microsoft.com/v1.0");
+        assert_eq!(config.auth.client_id, super::DEFAULT_CLIENT_ID);
+        assert_eq!(config.auth.tenant, "common");
+        assert_eq!(
+            config.auth.authorize_url,
+            "https://login.microsoftonline.com/common/oauth2/v2.0/authorize"
+        );
+        assert_eq!(
+            config.auth.token_url,
+            "https://login.microsoftonline.com/common/oauth2/v2.0/token"
+        );
+        assert_eq!(
+            config.auth.device_code_url,
+            "https://login.microsoftonline.com/common/oauth2/v2.0/devicecode"
+        );
+    }
+
+    #[test]
+    fn flags_override_config_file() {
+        let file = || -> ConfigFile {
+            toml::from_str(concat!(
+                "client-id = \"FILE-ID\"\n",
+                "tenant = \"contoso.onmicrosoft.com\"\n",
+                "token-url = \"https://login.example.com/token\"\n",
+                "graph-url = \"https://graph.example.com/v1.0/\"\n",
+            ))
+            .unwrap()
+        };
+        let config = Config::new(None, sign_in(None, None), file());
+        assert_eq!(config.graph_url, "https://graph.example.com/v1.0");
+        assert_eq!(config.auth.client_id, "FILE-ID");
+        assert_eq!(config.auth.tenant, "contoso.onmicrosoft.com");
+        assert_eq!(
+            config.auth.authorize_url,
+            "https://login.microsoftonline.com/contoso.onmicrosoft.com/oauth2/v2.0/authorize"
+        );
+        assert_eq!(config.auth.token_url, "https://login.example.com/token");
+
+        let mut flags = sign_in(Some("FLAG-ID"), Some("fabrikam"));
+        flags.token_url = Some("https://login.example.org/token".to_owned());
+        let config = Config::new(Some("http://localhost:8080".to_owned()), flags, file());
+        assert_eq!(config.graph_url, "http://localhost:8080");
+        assert_eq!(config.auth.client_id, "FLAG-ID");
+        assert_eq!(config.auth.tenant, "fabrikam");
+        assert_eq!(
+            config.auth.device_code_url,
+            "https://login.microsoftonline.com/fabrikam/oauth2/v2.0/devicecode"
+        );
+        assert_eq!(config.auth.token_url, "https://login.example.org/token");
+    }
+}
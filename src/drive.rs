@@ -0,0 +1,135 @@
+use serde_derive::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Quota {
+    pub total: u64,
+    pub used: u64,
+    pub remaining: u64,
+    pub deleted: u64,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Identity {
+    #[serde(rename = "displayName", default)]
+    pub display_name: Option<String>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Owner {
+    #[serde(default)]
+    pub user: Option<Identity>,
+    #[serde(default)]
+    pub group: Option<Identity>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Drive {
+    pub id: String,
+    #[serde(default)]
+    pub name: String,
+    #[serde(rename = "driveType")]
+    pub drive_type: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub owner: Option<Owner>,
+    // Not known for drives loaded from a cache saved before drive details were stored
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub quota: Option<Quota>,
+}
+
+#[derive(Deserialize)]
+pub struct DriveList {
+    pub value: Vec<Drive>,
+}
+
+impl Drive {
+    // Details for a cached drive where only the ID is known
+    pub fn unknown(drive_id: &str) -> Drive {
+        Drive {
+            id: drive_id.to_owned(),
+            name: String::new(),
+            drive_type: String::new(),
+            owner: None,
+            quota: None,
+        }
+    }
+
+    pub fn owner_name(&self) -> Option<&str> {
+        let owner = self.owner.as_ref()?;
+        owner
+            .user
+            .as_ref()
+            .or(owner.group.as_ref())?
+            .display_name
+            .as_deref()
+    }
+}
+
+// Drives to process, selected by command-line options. Empty lists select all drives.
+pub struct DriveFilter {
+    // drive IDs or names
+    pub drives: Vec<String>,
+    pub drive_types: Vec<String>,
+}
+
+impl DriveFilter {
+    pub fn matches(&self, drive: &Drive) -> bool {
+        (self.drives.is_empty()
+            || self
+                .drives
+                .iter()
+                .any(|selector| *selector == drive.id || *selector == drive.name))
+            && (self.drive_types.is_empty() || self.drive_types.contains(&drive.drive_type))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Drive, DriveFilter};
+    use serde_json::json;
+
+    fn drive() -> Drive {
+        let data = json!({
+            "id": "ID",
+            "name": "OneDrive",
+            "driveType": "personal",
+            "owner": {
+                "user": {
+                    "displayName": "NAME",
+                    "id": "USER"
+                }
+            },
+            "quota": {
+                "deleted": 0,
+                "remaining": 768,
+                "state": "normal",
+                "total": 1024,
+                "used": 256
+            }
+        })
+        .to_string();
+        serde_json::from_str(&data).unwrap()
+    }
+
+    #[test]
+    fn json_drive() {
+        let drive = drive();
+        assert_eq!(drive.id, "ID");
+        assert_eq!(drive.drive_type, "personal");
+        assert_eq!(drive.owner_name(), Some("NAME"));
+        assert_eq!(drive.quota.unwrap().used, 256);
+    }
+
+    #[test]
+    fn filter_drive() {
+        let drive = drive();
+        let filter = |drives: &[&str], drive_types: &[&str]| DriveFilter {
+            drives: drives.iter().map(|s| s.to_string()).collect(),
+            drive_types: drive_types.iter().map(|s| s.to_string()).collect(),
+        };
+        assert!(filter(&[], &[]).matches(&drive));
+        assert!(filter(&["ID"], &[]).matches(&drive));
+        assert!(filter(&["OneDrive"], &["personal"]).matches(&drive));
+        assert!(!filter(&["OTHER"], &[]).matches(&drive));
+        assert!(!filter(&["ID"], &["business"]).matches(&drive));
+    }
+}
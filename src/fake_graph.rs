@@ -0,0 +1,132 @@
+// An in-process stand-in for Microsoft Graph, serving a scripted sequence of responses.
+use serde_json::Value;
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use tiny_http::{Header, Response, Server};
+
+// Replaced by the base URL of the server in response headers and bodies, so that scripted
+// links point back to the fake server.
+const BASE: &str = "{base}";
+
+pub struct FakeResponse {
+    status: u16,
+    headers: Vec<(&'static str, String)>,
+    body: String,
+}
+
+impl FakeResponse {
+    fn json(body: Value) -> FakeResponse {
+        FakeResponse {
+            status: 200,
+            headers: vec![("Content-Type", "application/json".to_owned())],
+            body: body.to_string(),
+        }
+    }
+
+    // A delta page with a link to the next page at `next` on the server
+    pub fn next_page(items: Value, next: &str) -> FakeResponse {
+        FakeResponse::json(serde_json::json!({
+            "value": items,
+            "@odata.nextLink": format!("{}{}", BASE, next),
+        }))
+    }
+
+    // The last delta page, with a link for the next sync at `delta` on the server
+    pub fn last_page(items: Value, delta: &str) -> FakeResponse {
+        FakeResponse::json(serde_json::json!({
+            "value": items,
+            "@odata.deltaLink": format!("{}{}", BASE, delta),
+        }))
+    }
+
+    pub fn gone(location: &str) -> FakeResponse {
+        FakeResponse {
+            status: 410,
+            headers: vec![("Location", format!("{}{}", BASE, location))],
+            body: String::new(),
+        }
+    }
+
+    pub fn throttled(seconds: u64) -> FakeResponse {
+        FakeResponse {
+            status: 429,
+            headers: vec![("Retry-After", seconds.to_string())],
+            body: String::new(),
+        }
+    }
+
+    pub fn malformed() -> FakeResponse {
+        FakeResponse {
+            status: 200,
+            headers: vec![("Content-Type", "application/json".to_owned())],
+            body: r#"{"value": [{"id": "#.to_owned(),
+        }
+    }
+
+    pub fn error(status: u16, code: &str) -> FakeResponse {
+        FakeResponse {
+            status,
+            headers: vec![("Content-Type", "application/json".to_owned())],
+            body: serde_json::json!({
+                "error": {
+                    "code": code,
+                    "message": "scripted error",
+                }
+            })
+            .to_string(),
+        }
+    }
+}
+
+pub struct FakeGraph {
+    server: Arc<Server>,
+    base_url: String,
+    thread: JoinHandle<Vec<String>>,
+}
+
+impl FakeGraph {
+    // Start a server that answers each request with the next response in the script. The
+    // requested paths are returned by `finish`.
+    pub fn start(script: Vec<FakeResponse>) -> FakeGraph {
+        let server = Arc::new(Server::http("127.0.0.1:0").unwrap());
+        let base_url = format!("http://127.0.0.1:{}", server.server_addr().port());
+        let thread = {
+            let server = server.clone();
+            let base_url = base_url.clone();
+            std::thread::spawn(move || {
+                let mut requested = Vec::new();
+                for response in script {
+                    let request = match server.recv() {
+                        Ok(request) => request,
+                        // unblocked by `finish`
+                        Err(_) => break,
+                    };
+                    requested.push(request.url().to_owned());
+                    let mut reply = Response::from_string(response.body.replace(BASE, &base_url))
+                        .with_status_code(response.status);
+                    for (name, value) in response.headers {
+                        let value = value.replace(BASE, &base_url);
+                        reply.add_header(Header::from_bytes(name, value).unwrap());
+                    }
+                    request.respond(reply).unwrap();
+                }
+                requested
+            })
+        };
+        FakeGraph {
+            server,
+            base_url,
+            thread,
+        }
+    }
+
+    pub fn url(&self) -> &str {
+        &self.base_url
+    }
+
+    // Stop the server and return the paths that were requested.
+    pub fn finish(self) -> Vec<String> {
+        self.server.unblock();
+        self.thread.join().unwrap()
+    }
+}
@@ -0,0 +1,178 @@
+use crate::cli::FilterArgs;
+use eyre::Result;
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use std::path::Path;
+
+// Version control internals hold copies of other files, which are part of the repository format
+// and should not be modified individually. A `!` rule in the ignore file includes them again.
+const DEFAULT_IGNORE_RULES: &[&str] = &[
+    "**/.svn/pristine/**/*.svn-base",
+    "**/.git/objects/",
+    "**/.hg/store/",
+    "**/.bzr/repository/",
+];
+
+// Selects the files to look for duplicates in. Paths are relative to the drive root.
+pub(crate) struct ItemFilter {
+    ignore: Gitignore,
+    min_size: u64,
+    // an empty list includes all files
+    include: Option<GlobSet>,
+    exclude: GlobSet,
+    // lowercase, without the dot
+    extensions: Vec<String>,
+    // also group files without a hash by their size and name
+    probable: bool,
+}
+
+// `*` matches within one folder or file name, and `**` matches across folders
+fn glob_set(patterns: &[String]) -> Result<GlobSet> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        let glob = GlobBuilder::new(pattern.trim_start_matches('/'))
+            .literal_separator(true)
+            .build()?;
+        builder.add(glob);
+    }
+    Ok(builder.build()?)
+}
+
+// The built-in rules, then the rules in the file, then the rules given on the command line, so
+// that later rules can override earlier ones.
+fn ignore_rules(file: Option<&Path>, rules: &[String]) -> Result<Gitignore> {
+    let mut builder = GitignoreBuilder::new("");
+    for rule in DEFAULT_IGNORE_RULES {
+        builder.add_line(None, rule)?;
+    }
+    if let Some(file) = file.filter(|file| file.exists()) {
+        if let Some(error) = builder.add(file) {
+            return Err(error.into());
+        }
+    }
+    for rule in rules {
+        builder.add_line(None, rule)?;
+    }
+    Ok(builder.build()?)
+}
+
+impl Default for ItemFilter {
+    // Include every file
+    fn default() -> ItemFilter {
+        ItemFilter {
+            ignore: Gitignore::empty(),
+            min_size: 0,
+            include: None,
+            exclude: GlobSet::empty(),
+            extensions: Vec::new(),
+            probable: false,
+        }
+    }
+}
+
+impl ItemFilter {
+    pub(crate) fn new(args: &FilterArgs, ignore_file: Option<&Path>) -> Result<ItemFilter> {
+        Ok(ItemFilter {
+            ignore: ignore_rules(ignore_file, &args.ignore_rules)?,
+            min_size: args.min_size.unwrap_or(0),
+            include: if args.include.is_empty() {
+                None
+            } else {
+                Some(glob_set(&args.include)?)
+            },
+            exclude: glob_set(&args.exclude)?,
+            extensions: args
+                .extensions
+                .iter()
+                .map(|ext| ext.trim_start_matches('.').to_lowercase())
+                .collect(),
+            probable: args.probable,
+        })
+    }
+
+    pub(crate) fn probable(&self) -> bool {
+        self.probable
+    }
+
+    pub(crate) fn matches(&self, path: &str, size: u64) -> bool {
+        if size < self.min_size {
+            return false;
+        }
+        if !self.extensions.is_empty() {
+            let name = path.rsplit('/').next().unwrap_or(path);
+            match name.rsplit_once('.') {
+                Some((_, ext)) if self.extensions.contains(&ext.to_lowercase()) => {}
+                _ => return false,
+            }
+        }
+        if let Some(include) = &self.include {
+            if !include.is_match(path) {
+                return false;
+            }
+        }
+        if self.exclude.is_match(path) {
+            return false;
+        }
+        !self
+            .ignore
+            .matched_path_or_any_parents(path, false)
+            .is_ignore()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ItemFilter;
+    use crate::cli::FilterArgs;
+    use std::io::Write;
+
+    #[test]
+    fn filter_paths() {
+        let filter = ItemFilter::new(
+            &FilterArgs {
+                min_size: Some(1024),
+                include: vec!["Pictures/**".to_owned()],
+                exclude: vec!["**/Thumbs/**".to_owned(), "Pictures/*.jpg".to_owned()],
+                extensions: vec!["JPG".to_owned(), ".mov".to_owned()],
+                ignore_rules: Vec::new(),
+                probable: false,
+            },
+            None,
+        )
+        .unwrap();
+        assert!(filter.matches("Pictures/2015/a.jpg", 1024));
+        assert!(filter.matches("Pictures/2015/b.MOV", 4096));
+        assert!(!filter.matches("Pictures/2015/a.jpg", 1023));
+        assert!(!filter.matches("Pictures/2015/a.png", 4096));
+        assert!(!filter.matches("Pictures/2015/jpg", 4096));
+        assert!(!filter.matches("Documents/a.jpg", 4096));
+        assert!(!filter.matches("Pictures/Thumbs/a.jpg", 4096));
+        // `*` does not match across folders
+        assert!(!filter.matches("Pictures/a.jpg", 4096));
+        assert!(ItemFilter::default().matches("desktop.ini", 0));
+    }
+
+    #[test]
+    fn ignore_rules() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        writeln!(file, "# comment\nnode_modules/\n!**/.git/objects/").unwrap();
+        let args = FilterArgs {
+            min_size: None,
+            include: Vec::new(),
+            exclude: Vec::new(),
+            extensions: Vec::new(),
+            ignore_rules: vec!["*.tmp".to_owned(), "!keep.tmp".to_owned()],
+            probable: false,
+        };
+        let filter = ItemFilter::new(&args, None).unwrap();
+        assert!(!filter.matches("Code/repo/.svn/pristine/ab/abcd.svn-base", 1));
+        assert!(filter.matches("Code/repo/.svn/entries", 1));
+        assert!(!filter.matches("Code/repo/.git/objects/ab/cdef", 1));
+        assert!(filter.matches("Code/app/node_modules/x/index.js", 1));
+        assert!(!filter.matches("a/b.tmp", 1));
+        assert!(filter.matches("a/keep.tmp", 1));
+        let filter = ItemFilter::new(&args, Some(file.path())).unwrap();
+        assert!(filter.matches("Code/repo/.git/objects/ab/cdef", 1));
+        assert!(!filter.matches("Code/app/node_modules/x/index.js", 1));
+    }
+}
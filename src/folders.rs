@@ -0,0 +1,530 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::item::{DriveState, Item, ItemType};
+use crate::keeper::Keeper;
+use crate::size::{join_path, Buckets, FileRef};
+
+// Folders with fewer files are not reported, as their files are reported individually
+const MIN_FILES: usize = 2;
+// Fraction of their bytes that near-identical folders must share
+const MIN_SIMILARITY: f64 = 0.9;
+// Files with more copies are not used to find near-identical folders, as comparing every pair
+// of their folders takes too long
+const MAX_COPIES: usize = 16;
+
+pub(crate) struct FolderGroup<'a> {
+    // size of the largest folder
+    pub size: u64,
+    // bytes freed by keeping only one folder
+    pub wasted: u64,
+    // fraction of the bytes shared by the folders, 1.0 for identical folders
+    pub similarity: f64,
+    // the folder to keep first
+    pub folders: Vec<FileRef<'a>>,
+}
+
+pub(crate) struct DuplicateFolders<'a> {
+    state: &'a DriveState,
+    // Groups of duplicate folders, most wasted space first
+    pub groups: Vec<FolderGroup<'a>>,
+    // indexes of the groups containing each folder
+    member_of: HashMap<&'a str, Vec<usize>>,
+}
+
+impl<'a> DuplicateFolders<'a> {
+    // The reported groups with a folder above every item, with the distinct folders of the group
+    // that the items are in
+    fn spread<'b>(
+        &'b self,
+        items: impl Iterator<Item = &'b Item>,
+    ) -> HashMap<usize, HashSet<&'b str>> {
+        // for each item, the nearest folder containing it in each group
+        let mut folders = items.map(|item| {
+            let mut folders = HashMap::new();
+            for folder in self.state.ancestors(item) {
+                for group in self.member_of.get(folder.id.as_str()).into_iter().flatten() {
+                    folders.entry(*group).or_insert(folder.id.as_str());
+                }
+            }
+            folders
+        });
+        let mut spread = folders
+            .next()
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(group, folder)| (group, HashSet::from([folder])))
+            .collect::<HashMap<_, _>>();
+        for item_folders in folders {
+            spread.retain(|group, _| item_folders.contains_key(group));
+            for (group, seen) in spread.iter_mut() {
+                seen.insert(item_folders[group]);
+            }
+        }
+        spread
+    }
+
+    // The first reported group whose folders contain a group of duplicate files. Copies inside
+    // the same folder are still reported.
+    fn covering_group(&self, files: &[FileRef]) -> Option<&FolderGroup<'a>> {
+        self.spread(files.iter().map(|file| file.item))
+            .into_iter()
+            .filter(|(_, seen)| seen.len() > 1)
+            .map(|(group, _)| group)
+            .min()
+            .map(|group| &self.groups[group])
+    }
+
+    // Whether a group of duplicate files is contained in the folders of a reported group
+    pub(crate) fn covers(&self, files: &[FileRef]) -> bool {
+        self.covering_group(files).is_some()
+    }
+}
+
+// Part of the fingerprint of a folder
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+enum Entry {
+    File(u32),
+    Folder(u32),
+}
+
+struct Contents<'a> {
+    // sorted content IDs of every file in each folder and its subfolders
+    files: HashMap<&'a str, Vec<u32>>,
+    // ID of the arrangement of files and subfolders in each folder, ignoring names
+    layout: HashMap<&'a str, u32>,
+    layouts: HashMap<Vec<Entry>, u32>,
+}
+
+fn collect_contents<'a>(
+    state: &'a DriveState,
+    folder: &'a Item,
+    content_of: &HashMap<&str, u32>,
+    contents: &mut Contents<'a>,
+) {
+    let mut files = Vec::new();
+    let mut entries = Vec::new();
+    for child in state.children(folder) {
+        match child.item_type {
+            ItemType::File { .. } => {
+                if let Some(content) = content_of.get(child.id.as_str()) {
+                    files.push(*content);
+                    entries.push(Entry::File(*content));
+                }
+            }
+            ItemType::Folder {} | ItemType::Package {} => {
+                collect_contents(state, child, content_of, contents);
+                files.extend_from_slice(&contents.files[child.id.as_str()]);
+                entries.push(Entry::Folder(contents.layout[child.id.as_str()]));
+            }
+        }
+    }
+    files.sort_unstable();
+    entries.sort_unstable();
+    let next = contents.layouts.len() as u32;
+    let layout = *contents.layouts.entry(entries).or_insert(next);
+    contents.files.insert(&folder.id, files);
+    contents.layout.insert(&folder.id, layout);
+}
+
+// Bytes in both of two sorted lists of contents
+fn shared_bytes(a: &[u32], b: &[u32], sizes: &[u64]) -> u64 {
+    let (mut i, mut j) = (0, 0);
+    let mut shared = 0;
+    while i < a.len() && j < b.len() {
+        match a[i].cmp(&b[j]) {
+            std::cmp::Ordering::Less => i += 1,
+            std::cmp::Ordering::Greater => j += 1,
+            std::cmp::Ordering::Equal => {
+                shared += sizes[a[i] as usize];
+                i += 1;
+                j += 1;
+            }
+        }
+    }
+    shared
+}
+
+fn is_ancestor(state: &DriveState, folder: &Item, item: &Item) -> bool {
+    state
+        .ancestors(item)
+        .any(|ancestor| ancestor.id == folder.id)
+}
+
+// Find folders whose files have the same contents, or nearly the same contents, as another
+// folder. Folders inside a reported folder are only reported if they have other copies.
+// The `Keeper` chooses the folder to keep in each group, and the copies of files inside the
+// folders of a group are reordered to keep the copy in that folder.
+pub(crate) fn duplicate_folders<'a>(
+    state: &'a DriveState,
+    buckets: &mut Buckets<'a>,
+    keeper: &Keeper,
+) -> DuplicateFolders<'a> {
+    // number each distinct file content
+    let mut content_of = HashMap::new();
+    let mut sizes = Vec::new();
+    let mut copies = Vec::new();
+    for (size, files_by_hash) in &buckets.by_size {
+        for files in files_by_hash.values() {
+            let content = sizes.len() as u32;
+            sizes.push(*size);
+            for file in files {
+                content_of.insert(file.item.id.as_str(), content);
+            }
+            copies.push(files);
+        }
+    }
+    let mut contents = Contents {
+        files: HashMap::new(),
+        layout: HashMap::new(),
+        layouts: HashMap::new(),
+    };
+    if let Some(root) = state.root() {
+        collect_contents(state, root, &content_of, &mut contents);
+    }
+    let bytes = |files: &[u32]| files.iter().map(|c| sizes[*c as usize]).sum::<u64>();
+
+    let mut groups = Vec::new();
+    let mut by_layout = HashMap::<u32, Vec<&Item>>::new();
+    for (id, files) in &contents.files {
+        if files.len() >= MIN_FILES {
+            by_layout
+                .entry(contents.layout[id])
+                .or_default()
+                .push(&state.items[*id]);
+        }
+    }
+    for folders in by_layout.into_values() {
+        if folders.len() > 1 {
+            let size = bytes(&contents.files[folders[0].id.as_str()]);
+            let wasted = size * (folders.len() as u64 - 1);
+            groups.push((size, wasted, 1.0, folders));
+        }
+    }
+
+    // Near-identical folders have copies of the same files at the same depth below them
+    let mut pairs = HashSet::new();
+    for files in copies {
+        if files.len() < 2 || files.len() > MAX_COPIES {
+            continue;
+        }
+        for (i, a) in files.iter().enumerate() {
+            for b in &files[i + 1..] {
+                for (x, y) in state.ancestors(a.item).zip(state.ancestors(b.item)) {
+                    if x.id == y.id {
+                        break;
+                    }
+                    if x.id < y.id {
+                        pairs.insert((x.id.as_str(), y.id.as_str()));
+                    } else {
+                        pairs.insert((y.id.as_str(), x.id.as_str()));
+                    }
+                }
+            }
+        }
+    }
+    for (x, y) in pairs {
+        if contents.layout[x] == contents.layout[y] {
+            continue;
+        }
+        let (a, b) = (&contents.files[x], &contents.files[y]);
+        if a.len().min(b.len()) < MIN_FILES {
+            continue;
+        }
+        if (a.len().min(b.len()) as f64) < a.len().max(b.len()) as f64 * MIN_SIMILARITY {
+            continue;
+        }
+        let (x, y) = (&state.items[x], &state.items[y]);
+        if is_ancestor(state, x, y) || is_ancestor(state, y, x) {
+            continue;
+        }
+        let shared = shared_bytes(a, b, &sizes);
+        let (a_bytes, b_bytes) = (bytes(a), bytes(b));
+        let similarity = shared as f64 / (a_bytes + b_bytes - shared) as f64;
+        if similarity >= MIN_SIMILARITY {
+            let (x, y) = if a_bytes >= b_bytes { (x, y) } else { (y, x) };
+            groups.push((a_bytes.max(b_bytes), shared, similarity, vec![x, y]));
+        }
+    }
+
+    // A group of outer folders wastes at least as much as a group of their subfolders. Sort
+    // outer folders first, so that groups of their subfolders can be skipped.
+    let mut groups = groups
+        .into_iter()
+        .map(|(size, wasted, similarity, folders)| {
+            let depth = state.ancestors(folders[0]).count();
+            (wasted, depth, size, similarity, folders)
+        })
+        .collect::<Vec<_>>();
+    groups.sort_by(|a, b| {
+        (b.0, a.1, &a.4[0].id)
+            .cmp(&(a.0, b.1, &b.4[0].id))
+            .then(b.3.total_cmp(&a.3))
+    });
+    let mut result = DuplicateFolders {
+        state,
+        groups: Vec::new(),
+        member_of: HashMap::new(),
+    };
+    for (wasted, _, size, similarity, folders) in groups {
+        // skip the group if each folder is inside a different folder of the same group, at
+        // any depth
+        let inside_group = result
+            .spread(folders.iter().copied())
+            .values()
+            .any(|seen| seen.len() == folders.len());
+        if inside_group {
+            continue;
+        }
+        let index = result.groups.len();
+        let mut refs = Vec::with_capacity(folders.len());
+        for folder in folders {
+            result
+                .member_of
+                .entry(folder.id.as_str())
+                .or_default()
+                .push(index);
+            let path = join_path(&state.parent_path(folder).unwrap_or_default(), &folder.name);
+            refs.push(FileRef { item: folder, path });
+        }
+        // near-identical folders are listed largest first
+        if similarity >= 1.0 {
+            refs.sort_unstable_by(|a, b| a.path.cmp(&b.path));
+        }
+        keeper.order(&mut refs);
+        result.groups.push(FolderGroup {
+            size,
+            wasted,
+            similarity,
+            folders: refs,
+        });
+    }
+    for files in buckets.by_size.values_mut().flat_map(HashMap::values_mut) {
+        let kept = match result.covering_group(files) {
+            Some(group) => group.folders[0].item,
+            None => continue,
+        };
+        files.sort_by_key(|file| !is_ancestor(state, kept, file.item));
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::duplicate_folders;
+    use crate::cli::{KeepPolicy, KeeperArgs};
+    use crate::filter::ItemFilter;
+    use crate::item::DriveState;
+    use crate::keeper::Keeper;
+    use crate::size::bucket_by_size;
+    use serde_json::json;
+
+    fn add(state: &mut DriveState, id: &str, parent: &str, size: Option<u64>) {
+        let mut data = json!({
+            "id": id,
+            "name": id.rsplit('/').next().unwrap(),
+            "parentReference": {
+                "id": parent,
+                "driveType": "personal"
+            },
+        });
+        match size {
+            Some(size) => {
+                data["size"] = json!(size);
+                // files with the same name and size have the same contents
+                data["file"] = json!({"hashes": {"sha1Hash": format!("{}{}", data["name"], size)}});
+            }
+            None => data["folder"] = json!({}),
+        }
+        state.upsert(serde_json::from_value(data).unwrap());
+    }
+
+    fn drive() -> DriveState {
+        let mut state = DriveState::default();
+        state.upsert(
+            serde_json::from_value(json!({
+                "id": "ROOT",
+                "name": "root",
+                "parentReference": {"driveType": "personal"},
+                "folder": {},
+                "root": {}
+            }))
+            .unwrap(),
+        );
+        for folder in ["Pictures", "Family Photos", "Other"] {
+            add(&mut state, folder, "ROOT", None);
+        }
+        for folder in ["Pictures/2015", "Family Photos/2015", "Other/2016"] {
+            add(&mut state, folder, folder.split('/').next().unwrap(), None);
+        }
+        state
+    }
+
+    #[test]
+    fn identical_folders() {
+        let mut state = drive();
+        for folder in ["Pictures/2015", "Family Photos/2015"] {
+            for name in ["a.jpg", "b.jpg", "c.jpg"] {
+                add(
+                    &mut state,
+                    &format!("{}/{}", folder, name),
+                    folder,
+                    Some(1000),
+                );
+            }
+        }
+        add(&mut state, "Pictures/d.jpg", "Pictures", Some(5000));
+        add(&mut state, "Other/2016/a.jpg", "Other/2016", Some(1000));
+        let mut buckets = bucket_by_size(&state, &ItemFilter::default(), &Keeper::default());
+        let folders = duplicate_folders(&state, &mut buckets, &Keeper::default());
+        assert_eq!(folders.groups.len(), 1);
+        let group = &folders.groups[0];
+        assert_eq!(group.size, 3000);
+        assert_eq!(group.similarity, 1.0);
+        let mut paths = group
+            .folders
+            .iter()
+            .map(|f| f.path.as_str())
+            .collect::<Vec<_>>();
+        paths.sort_unstable();
+        assert_eq!(paths, ["Family Photos/2015", "Pictures/2015"]);
+        // a.jpg has a third copy outside the folders
+        let reported = buckets
+            .duplicates()
+            .filter(|(_, _, files)| !folders.covers(files))
+            .map(|(_, _, files)| files.len())
+            .collect::<Vec<_>>();
+        assert_eq!(reported, [3]);
+    }
+
+    #[test]
+    fn nested_identical_folders() {
+        let mut state = drive();
+        for folder in ["Pictures/2015", "Family Photos/2015"] {
+            let summer = format!("{}/Summer", folder);
+            add(&mut state, &summer, folder, None);
+            for name in ["a.jpg", "b.jpg"] {
+                add(
+                    &mut state,
+                    &format!("{}/{}", summer, name),
+                    &summer,
+                    Some(1000),
+                );
+            }
+        }
+        let mut buckets = bucket_by_size(&state, &ItemFilter::default(), &Keeper::default());
+        let folders = duplicate_folders(&state, &mut buckets, &Keeper::default());
+        // the folders below Pictures and Family Photos are not reported again
+        assert_eq!(folders.groups.len(), 1);
+        let mut paths = folders.groups[0]
+            .folders
+            .iter()
+            .map(|f| f.path.as_str())
+            .collect::<Vec<_>>();
+        paths.sort_unstable();
+        assert_eq!(paths, ["Family Photos", "Pictures"]);
+    }
+
+    #[test]
+    fn keep_copies_in_kept_folder() {
+        let mut state = drive();
+        for folder in ["Pictures/2015", "Family Photos/2015"] {
+            for name in ["b.jpg", "c.jpg"] {
+                add(
+                    &mut state,
+                    &format!("{}/{}", folder, name),
+                    folder,
+                    Some(1000),
+                );
+            }
+        }
+        let created = Some("2010-01-01T00:00:00Z".to_owned());
+        state.items.get_mut("Pictures").unwrap().created = created;
+        // the oldest copy of b.jpg is in the folder that is not kept
+        let created = Some("2005-01-01T00:00:00Z".to_owned());
+        state
+            .items
+            .get_mut("Family Photos/2015/b.jpg")
+            .unwrap()
+            .created = created;
+        let keeper = Keeper::new(&KeeperArgs {
+            policies: vec![KeepPolicy::Oldest],
+            preferred_folders: Vec::new(),
+        });
+        let mut buckets = bucket_by_size(&state, &ItemFilter::default(), &keeper);
+        let folders = duplicate_folders(&state, &mut buckets, &keeper);
+        assert_eq!(folders.groups.len(), 1);
+        assert_eq!(folders.groups[0].folders[0].path, "Pictures");
+        let mut kept = buckets
+            .duplicates()
+            .map(|(_, _, files)| files[0].path.as_str())
+            .collect::<Vec<_>>();
+        kept.sort_unstable();
+        assert_eq!(kept, ["Pictures/2015/b.jpg", "Pictures/2015/c.jpg"]);
+    }
+
+    #[test]
+    fn keep_folder_outside_camera_roll() {
+        let mut state = drive();
+        for folder in ["Camera Roll", "Saved"] {
+            add(&mut state, folder, "ROOT", None);
+            for name in ["a.jpg", "b.jpg"] {
+                add(
+                    &mut state,
+                    &format!("{}/{}", folder, name),
+                    folder,
+                    Some(1000),
+                );
+            }
+        }
+        let keeper = Keeper::new(&KeeperArgs {
+            policies: vec![KeepPolicy::NotCameraRoll],
+            preferred_folders: Vec::new(),
+        });
+        let mut buckets = bucket_by_size(&state, &ItemFilter::default(), &keeper);
+        let folders = duplicate_folders(&state, &mut buckets, &keeper);
+        assert_eq!(folders.groups.len(), 1);
+        let paths = folders.groups[0]
+            .folders
+            .iter()
+            .map(|f| f.path.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(paths, ["Saved", "Camera Roll"]);
+    }
+
+    #[test]
+    fn near_identical_folders() {
+        let mut state = drive();
+        for folder in ["Pictures/2015", "Family Photos/2015"] {
+            for name in 'a'..='t' {
+                add(
+                    &mut state,
+                    &format!("{}/{}.jpg", folder, name),
+                    folder,
+                    Some(1000),
+                );
+            }
+        }
+        add(
+            &mut state,
+            "Pictures/2015/u.jpg",
+            "Pictures/2015",
+            Some(1000),
+        );
+        let mut buckets = bucket_by_size(&state, &ItemFilter::default(), &Keeper::default());
+        let folders = duplicate_folders(&state, &mut buckets, &Keeper::default());
+        // the parent folders contain only these folders, so they are reported instead
+        assert_eq!(folders.groups.len(), 1);
+        let group = &folders.groups[0];
+        assert_eq!(group.size, 21000);
+        assert!((group.similarity - 20.0 / 21.0).abs() < 1e-9);
+        assert_eq!(group.folders[0].path, "Pictures");
+        assert_eq!(group.folders[1].path, "Family Photos");
+        assert_eq!(
+            buckets
+                .duplicates()
+                .filter(|(_, _, files)| !folders.covers(files))
+                .count(),
+            0
+        );
+    }
+}
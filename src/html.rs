@@ -0,0 +1,503 @@
+use crate::drive::{Drive, Quota};
+use crate::filter::ItemFilter;
+use crate::folders::duplicate_folders;
+use crate::item::DriveSnapshot;
+use crate::keeper::{action, Keeper};
+use crate::report::{folder_tree, history_as_string, similarity_as_string, Folder};
+use crate::size::{bucket_by_size, item_hashes, percent, size_as_string, wasted};
+use std::fmt::Write;
+
+// The report is a single file with inline styles and scripts, so that it can be emailed or
+// opened offline.
+const STYLE: &str = r#"
+body { font-family: sans-serif; margin: 2em; color: #222; }
+h2 { border-bottom: 1px solid #ccc; }
+table { border-collapse: collapse; }
+th, td { padding: 0.2em 0.8em; text-align: left; vertical-align: top; }
+th.sortable { cursor: pointer; text-decoration: underline dotted; }
+td.number, th.number { text-align: right; }
+tr:nth-child(even) { background: #f4f4f4; }
+summary { cursor: pointer; }
+.bar { width: 30em; height: 1em; background: #ddd; }
+.bar div { height: 100%; background: #4a7fc1; }
+svg text { font-size: 11px; pointer-events: none; }
+.keep { color: #2a7a2a; font-weight: bold; }
+.remove { color: #a33; }
+"#;
+
+const SCRIPT: &str = r#"
+function sortTable(header) {
+    const table = header.closest("table");
+    const column = Array.from(header.parentNode.children).indexOf(header);
+    const ascending = header.dataset.order !== "ascending";
+    header.dataset.order = ascending ? "ascending" : "descending";
+    const body = table.tBodies[0];
+    const rows = Array.from(body.rows);
+    rows.sort((a, b) => {
+        const x = a.cells[column].dataset.value;
+        const y = b.cells[column].dataset.value;
+        const order = isNaN(x) ? x.localeCompare(y) : x - y;
+        return ascending ? order : -order;
+    });
+    rows.forEach(row => body.appendChild(row));
+}
+"#;
+
+const TREEMAP_WIDTH: f64 = 960.0;
+const TREEMAP_HEIGHT: f64 = 540.0;
+const TREEMAP_DEPTH: usize = 2;
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[derive(Clone, Copy)]
+struct Rect {
+    x: f64,
+    y: f64,
+    w: f64,
+    h: f64,
+}
+
+// Aspect ratio of the worst rectangle in a row of areas laid out along a side of length `side`
+fn worst_ratio(row: &[f64], side: f64) -> f64 {
+    let sum: f64 = row.iter().sum();
+    let max = row.iter().cloned().fold(f64::MIN, f64::max);
+    let min = row.iter().cloned().fold(f64::MAX, f64::min);
+    let side2 = side * side;
+    let sum2 = sum * sum;
+    f64::max(side2 * max / sum2, sum2 / (side2 * min))
+}
+
+// Squarified treemap layout (Bruls, Huizing and van Wijk). The areas must be sorted largest
+// first, and must add up to the area of the rectangle.
+fn squarify(areas: &[f64], mut rect: Rect) -> Vec<Rect> {
+    let mut rects = Vec::with_capacity(areas.len());
+    let mut start = 0;
+    while start < areas.len() {
+        let side = rect.w.min(rect.h);
+        let mut end = start + 1;
+        let mut ratio = worst_ratio(&areas[start..end], side);
+        while end < areas.len() {
+            let next = worst_ratio(&areas[start..=end], side);
+            if next > ratio {
+                break;
+            }
+            ratio = next;
+            end += 1;
+        }
+        let row = &areas[start..end];
+        let sum: f64 = row.iter().sum();
+        if rect.w >= rect.h {
+            // lay out the row as a column on the left
+            let w = if rect.h > 0.0 { sum / rect.h } else { 0.0 };
+            let mut y = rect.y;
+            for area in row {
+                let h = if w > 0.0 { area / w } else { 0.0 };
+                rects.push(Rect { x: rect.x, y, w, h });
+                y += h;
+            }
+            rect.x += w;
+            rect.w -= w;
+        } else {
+            // lay out the row along the top
+            let h = if rect.w > 0.0 { sum / rect.w } else { 0.0 };
+            let mut x = rect.x;
+            for area in row {
+                let w = if h > 0.0 { area / h } else { 0.0 };
+                rects.push(Rect { x, y: rect.y, w, h });
+                x += w;
+            }
+            rect.y += h;
+            rect.h -= h;
+        }
+        start = end;
+    }
+    rects
+}
+
+fn treemap(svg: &mut String, folder: &Folder, path: &str, rect: Rect, depth: usize, hue: usize) {
+    let mut children = folder
+        .children
+        .iter()
+        .filter(|(_, child)| child.size > 0)
+        .map(|(name, child)| (Some(*name), child.size, Some(child)))
+        .collect::<Vec<_>>();
+    let files_size = folder.size - children.iter().map(|(_, size, _)| size).sum::<u64>();
+    if files_size > 0 {
+        // space used by files directly in this folder
+        children.push((None, files_size, None));
+    }
+    children.sort_by_key(|(_, size, _)| std::cmp::Reverse(*size));
+    let scale = rect.w * rect.h / folder.size as f64;
+    let areas = children
+        .iter()
+        .map(|(_, size, _)| *size as f64 * scale)
+        .collect::<Vec<_>>();
+    for (index, ((name, size, child), child_rect)) in
+        children.iter().zip(squarify(&areas, rect)).enumerate()
+    {
+        let hue = if depth == 0 { index * 47 % 360 } else { hue };
+        let (child_path, fill) = match name {
+            Some(name) if path.is_empty() => (name.to_string(), format!("hsl({}, 55%, 65%)", hue)),
+            Some(name) => (
+                format!("{}/{}", path, name),
+                format!("hsl({}, 55%, {}%)", hue, 65 + depth * 10),
+            ),
+            None if path.is_empty() => ("(files)".to_owned(), "#ccc".to_owned()),
+            None => (format!("{}/(files)", path), "#ccc".to_owned()),
+        };
+        writeln!(
+            svg,
+            r#"<rect x="{:.1}" y="{:.1}" width="{:.1}" height="{:.1}" fill="{}" stroke="white"><title>{} - {}</title></rect>"#,
+            child_rect.x,
+            child_rect.y,
+            child_rect.w,
+            child_rect.h,
+            fill,
+            escape(&child_path),
+            size_as_string(*size)
+        )
+        .unwrap();
+        if let Some(child) = child {
+            if depth + 1 < TREEMAP_DEPTH {
+                let inner = Rect {
+                    x: child_rect.x + 2.0,
+                    y: child_rect.y + 14.0,
+                    w: (child_rect.w - 4.0).max(0.0),
+                    h: (child_rect.h - 16.0).max(0.0),
+                };
+                if inner.w > 0.0 && inner.h > 0.0 {
+                    treemap(svg, child, &child_path, inner, depth + 1, hue);
+                }
+            }
+            if depth == 0 && child_rect.w > 40.0 && child_rect.h > 14.0 {
+                writeln!(
+                    svg,
+                    r#"<text x="{:.1}" y="{:.1}">{}</text>"#,
+                    child_rect.x + 3.0,
+                    child_rect.y + 11.0,
+                    escape(name.unwrap_or_default())
+                )
+                .unwrap();
+            }
+        }
+    }
+}
+
+fn quota_section(html: &mut String, quota: &Quota) {
+    let used = percent(quota.used, quota.total);
+    writeln!(
+        html,
+        r#"<table>
+<tr><th>Total</th><td class="number">{}</td></tr>
+<tr><th>Free</th><td class="number">{}</td></tr>
+<tr><th>Used</th><td class="number">{}</td><td>{:.2}%</td></tr>
+<tr><th>Pending deletion</th><td class="number">{}</td></tr>
+</table>
+<div class="bar"><div style="width: {:.2}%"></div></div>"#,
+        size_as_string(quota.total),
+        size_as_string(quota.remaining),
+        size_as_string(quota.used),
+        used,
+        size_as_string(quota.deleted),
+        used,
+    )
+    .unwrap();
+}
+
+fn duplicates_section(
+    html: &mut String,
+    snapshot: &DriveSnapshot,
+    filter: &ItemFilter,
+    keeper: &Keeper,
+) {
+    let mut buckets = bucket_by_size(&snapshot.state, filter, keeper);
+    let folders = duplicate_folders(&snapshot.state, &mut buckets, keeper);
+    let reclaimable = buckets.reclaimable();
+    writeln!(
+        html,
+        "<p>{} files in {} folders, {} reclaimable by removing duplicates</p>",
+        buckets.file_count,
+        buckets.folder_count,
+        size_as_string(reclaimable.total)
+    )
+    .unwrap();
+    if !reclaimable.by_folder.is_empty() {
+        html.push_str(
+            r#"<table>
+<thead><tr><th>Top-level folder</th><th class="number">Reclaimable</th></tr></thead>
+<tbody>
+"#,
+        );
+        for (folder, wasted) in &reclaimable.by_folder {
+            writeln!(
+                html,
+                r#"<tr><td>{}</td><td class="number">{}</td></tr>"#,
+                escape(folder),
+                size_as_string(*wasted)
+            )
+            .unwrap();
+        }
+        html.push_str("</tbody>\n</table>\n");
+    }
+    if !folders.groups.is_empty() {
+        html.push_str(
+            r#"<h4>Folders</h4>
+<table>
+<thead><tr>
+<th class="sortable number" onclick="sortTable(this)">Size</th>
+<th class="sortable number" onclick="sortTable(this)">Similarity</th>
+<th class="sortable number" onclick="sortTable(this)">Reclaimable</th>
+<th class="sortable" onclick="sortTable(this)">Folders</th>
+</tr></thead>
+<tbody>
+"#,
+        );
+        for group in &folders.groups {
+            writeln!(
+                html,
+                r#"<tr><td class="number" data-value="{}">{}</td><td class="number" data-value="{}">{}</td><td class="number" data-value="{}">{}</td><td data-value="{}">"#,
+                group.size,
+                size_as_string(group.size),
+                group.similarity,
+                similarity_as_string(group.similarity),
+                group.wasted,
+                size_as_string(group.wasted),
+                escape(&group.folders[0].path),
+            )
+            .unwrap();
+            for (copy, folder) in group.folders.iter().enumerate() {
+                writeln!(
+                    html,
+                    r#"<span class="{action}">{action}</span> {}/<br>"#,
+                    escape(&folder.path),
+                    action = action(copy),
+                )
+                .unwrap();
+            }
+            html.push_str("</td></tr>\n");
+        }
+        html.push_str("</tbody>\n</table>\n<h4>Files</h4>\n");
+    }
+    html.push_str(
+        r#"<table>
+<thead><tr>
+<th class="sortable number" onclick="sortTable(this)">Size</th>
+<th class="sortable number" onclick="sortTable(this)">Copies</th>
+<th class="sortable number" onclick="sortTable(this)">Reclaimable</th>
+<th class="sortable" onclick="sortTable(this)">Files</th>
+</tr></thead>
+<tbody>
+"#,
+    );
+    for (size, _, files) in buckets
+        .duplicates()
+        .filter(|(_, _, files)| !folders.covers(files))
+    {
+        let wasted = wasted(size, files.len());
+        writeln!(
+            html,
+            r#"<tr><td class="number" data-value="{}">{}</td><td class="number" data-value="{}">{}</td><td class="number" data-value="{}">{}</td><td data-value="{}"><details><summary>{}</summary>"#,
+            size,
+            size_as_string(size),
+            files.len(),
+            files.len(),
+            wasted,
+            size_as_string(wasted),
+            escape(&files[0].path),
+            escape(&files[0].path),
+        )
+        .unwrap();
+        for (copy, file) in files.iter().enumerate() {
+            writeln!(
+                html,
+                r#"<span class="{action}">{action}</span> {} <small>{}</small><br>"#,
+                escape(&file.path),
+                escape(&history_as_string(file.item)),
+                action = action(copy),
+            )
+            .unwrap();
+        }
+        html.push_str("</details></td></tr>\n");
+    }
+    html.push_str("</tbody>\n</table>\n");
+    if !buckets.mismatches.is_empty() {
+        html.push_str(
+            r#"<h4>Hash mismatches</h4>
+<p>Files that share one hash but not another. One of the copies may be corrupted.</p>
+<table>
+<thead><tr><th class="number">Size</th><th>File</th><th>Hashes</th></tr></thead>
+<tbody>
+"#,
+        );
+        for (size, files) in &buckets.mismatches {
+            for file in files {
+                let hashes = item_hashes(file.item)
+                    .iter()
+                    .map(|hash| format!("{} {}", hash.kind(), escape(hash.value())))
+                    .collect::<Vec<_>>();
+                writeln!(
+                    html,
+                    r#"<tr><td class="number">{}</td><td>{}</td><td>{}</td></tr>"#,
+                    size_as_string(*size),
+                    escape(&file.path),
+                    hashes.join("<br>")
+                )
+                .unwrap();
+            }
+        }
+        html.push_str("</tbody>\n</table>\n");
+    }
+    if buckets.probable_duplicates().next().is_some() {
+        html.push_str(
+            r#"<h4>Probable duplicates</h4>
+<p>Files without a hash, with the same size and name. Their contents have not been compared.</p>
+<table>
+<thead><tr>
+<th class="sortable number" onclick="sortTable(this)">Size</th>
+<th class="sortable number" onclick="sortTable(this)">Copies</th>
+<th class="sortable" onclick="sortTable(this)">Files</th>
+</tr></thead>
+<tbody>
+"#,
+        );
+        for (size, files) in buckets.probable_duplicates() {
+            writeln!(
+                html,
+                r#"<tr><td class="number" data-value="{}">{}</td><td class="number" data-value="{}">{}</td><td data-value="{}"><details><summary>{}</summary>"#,
+                size,
+                size_as_string(size),
+                files.len(),
+                files.len(),
+                escape(&files[0].path),
+                escape(&files[0].path),
+            )
+            .unwrap();
+            for file in files {
+                writeln!(html, "{}<br>", escape(&file.path)).unwrap();
+            }
+            html.push_str("</details></td></tr>\n");
+        }
+        html.push_str("</tbody>\n</table>\n");
+    }
+}
+
+pub fn drive_section(
+    html: &mut String,
+    drive: &Drive,
+    snapshot: &DriveSnapshot,
+    filter: &ItemFilter,
+    keeper: &Keeper,
+) {
+    writeln!(
+        html,
+        "<h2>{} <small>({}, {})</small></h2>",
+        escape(&drive.name),
+        escape(&drive.drive_type),
+        escape(&drive.id)
+    )
+    .unwrap();
+    if let Some(quota) = &drive.quota {
+        html.push_str("<h3>Usage</h3>\n");
+        quota_section(html, quota);
+    }
+    html.push_str("<h3>Space used by folder</h3>\n");
+    let root = folder_tree(snapshot, TREEMAP_DEPTH);
+    writeln!(
+        html,
+        r#"<svg viewBox="0 0 {w} {h}" width="{w}" height="{h}">"#,
+        w = TREEMAP_WIDTH,
+        h = TREEMAP_HEIGHT
+    )
+    .unwrap();
+    if root.size > 0 {
+        let rect = Rect {
+            x: 0.0,
+            y: 0.0,
+            w: TREEMAP_WIDTH,
+            h: TREEMAP_HEIGHT,
+        };
+        treemap(html, &root, "", rect, 0, 0);
+    }
+    html.push_str("</svg>\n");
+    html.push_str("<h3>Duplicates</h3>\n");
+    duplicates_section(html, snapshot, filter, keeper);
+}
+
+pub fn document(sections: &str) -> String {
+    format!(
+        r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>OneDrive report</title>
+<style>{}</style>
+<script>{}</script>
+</head>
+<body>
+<h1>OneDrive report</h1>
+{}
+</body>
+</html>
+"#,
+        STYLE, SCRIPT, sections
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{escape, quota_section, squarify, Rect};
+    use crate::drive::Quota;
+
+    #[test]
+    fn escape_html() {
+        assert_eq!(
+            escape("<a href='x'>&</a>"),
+            "&lt;a href=&#39;x&#39;&gt;&amp;&lt;/a&gt;"
+        );
+    }
+
+    #[test]
+    fn empty_quota() {
+        let mut html = String::new();
+        let quota = Quota {
+            total: 0,
+            used: 0,
+            remaining: 0,
+            deleted: 0,
+        };
+        quota_section(&mut html, &quota);
+        assert!(html.contains("<td>0.00%</td>"));
+        assert!(!html.contains("NaN"));
+    }
+
+    #[test]
+    fn squarify_fills_rect() {
+        let rect = Rect {
+            x: 0.0,
+            y: 0.0,
+            w: 6.0,
+            h: 4.0,
+        };
+        let areas = [6.0, 6.0, 4.0, 3.0, 2.0, 2.0, 1.0];
+        let rects = squarify(&areas, rect);
+        assert_eq!(rects.len(), areas.len());
+        for (area, rect) in areas.iter().zip(&rects) {
+            assert!((rect.w * rect.h - area).abs() < 1e-9);
+            assert!(rect.x >= 0.0 && rect.x + rect.w <= 6.0 + 1e-9);
+            assert!(rect.y >= 0.0 && rect.y + rect.h <= 4.0 + 1e-9);
+        }
+    }
+}
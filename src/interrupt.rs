@@ -0,0 +1,37 @@
+use eyre::Result;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+// Exit status after Ctrl-C, matching a shell process killed by SIGINT
+pub const EXIT_STATUS: i32 = 130;
+
+// Set by the first Ctrl-C during a sync, which then stops at the end of the current page
+pub static STOP: AtomicBool = AtomicBool::new(false);
+static SYNCING: AtomicBool = AtomicBool::new(false);
+
+// While a sync is running, the first Ctrl-C asks it to stop so that its progress can be saved.
+// At any other time, or on a second Ctrl-C, the process exits immediately.
+pub fn install() -> Result<()> {
+    ctrlc::set_handler(|| {
+        if SYNCING.load(Ordering::SeqCst) && !STOP.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        std::process::exit(EXIT_STATUS);
+    })?;
+    Ok(())
+}
+
+// Marks a sync as running until dropped
+pub struct Syncing;
+
+impl Syncing {
+    pub fn start() -> Syncing {
+        SYNCING.store(true, Ordering::SeqCst);
+        Syncing
+    }
+}
+
+impl Drop for Syncing {
+    fn drop(&mut self) {
+        SYNCING.store(false, Ordering::SeqCst);
+    }
+}
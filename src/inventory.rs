@@ -0,0 +1,221 @@
+use crate::filter::ItemFilter;
+use crate::folders::duplicate_folders;
+use crate::item::{DriveSnapshot, ItemType};
+use crate::keeper::{action, Keeper};
+use crate::size::bucket_by_size;
+use eyre::Result;
+use serde_derive::Serialize;
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::Path;
+
+#[derive(Serialize)]
+struct InventoryRow<'a> {
+    drive_id: &'a str,
+    id: &'a str,
+    // empty if the parent has been deleted
+    parent_path: String,
+    name: &'a str,
+    #[serde(rename = "type")]
+    item_type: &'static str,
+    size: u64,
+    sha1: Option<&'a str>,
+    quick_xor: Option<&'a str>,
+    // "keep" or "remove" for copies of a duplicate file or folder
+    duplicate: Option<&'static str>,
+}
+
+pub fn writer(output: Option<&Path>) -> Result<csv::Writer<Box<dyn Write>>> {
+    let output: Box<dyn Write> = match output {
+        Some(path) => Box::new(std::fs::File::create(path)?),
+        None => Box::new(std::io::stdout()),
+    };
+    Ok(csv::Writer::from_writer(output))
+}
+
+// Write a row for every file and folder in the drive, sorted by path.
+pub(crate) fn write_inventory<W: Write>(
+    writer: &mut csv::Writer<W>,
+    drive_id: &str,
+    snapshot: &DriveSnapshot,
+    filter: &ItemFilter,
+    keeper: &Keeper,
+) -> Result<()> {
+    let mut buckets = bucket_by_size(&snapshot.state, filter, keeper);
+    let folders = duplicate_folders(&snapshot.state, &mut buckets, keeper);
+    let actions = buckets
+        .duplicates()
+        .map(|(_, _, files)| files)
+        .chain(folders.groups.iter().map(|group| group.folders.as_slice()))
+        .flat_map(|files| files.iter().enumerate())
+        .map(|(copy, file)| (file.item.id.as_str(), action(copy)))
+        .collect::<HashMap<_, _>>();
+    let mut rows = snapshot
+        .state
+        .items
+        .values()
+        .filter(|item| item.root.is_none())
+        .map(|item| {
+            let (item_type, hashes) = match &item.item_type {
+                ItemType::File { hashes } => ("file", hashes.as_ref()),
+                ItemType::Folder {} => ("folder", None),
+                ItemType::Package {} => ("package", None),
+            };
+            InventoryRow {
+                drive_id,
+                id: &item.id,
+                parent_path: snapshot.state.parent_path(item).unwrap_or_default(),
+                name: &item.name,
+                item_type,
+                size: item.size,
+                sha1: hashes.and_then(|hashes| hashes.sha.as_deref()),
+                quick_xor: hashes.and_then(|hashes| hashes.xor.as_deref()),
+                duplicate: actions.get(item.id.as_str()).copied(),
+            }
+        })
+        .collect::<Vec<_>>();
+    rows.sort_unstable_by(|a, b| (&a.parent_path, a.name).cmp(&(&b.parent_path, b.name)));
+    for row in rows {
+        writer.serialize(row)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::write_inventory;
+    use crate::filter::ItemFilter;
+    use crate::item::{DriveSnapshot, Item};
+    use crate::keeper::Keeper;
+    use serde_json::json;
+
+    #[test]
+    fn csv_inventory() {
+        let mut snapshot = DriveSnapshot::default("https://graph.example.com", "DRIVE");
+        for data in [
+            json!({
+                "id": "ROOT",
+                "name": "root",
+                "size": 8192,
+                "parentReference": {
+                    "driveType": "personal"
+                },
+                "folder": {},
+                "root": {}
+            }),
+            json!({
+                "id": "FILE",
+                "name": "a, b.txt",
+                "size": 8192,
+                "parentReference": {
+                    "id": "FOLDER",
+                    "driveType": "personal"
+                },
+                "file": {
+                    "hashes": {
+                        "sha1Hash": "9784E164A3626978D838EE21A0319C0DFB39001B"
+                    }
+                }
+            }),
+            json!({
+                "id": "FOLDER",
+                "name": "Documents",
+                "size": 8192,
+                "parentReference": {
+                    "id": "ROOT",
+                    "driveType": "personal"
+                },
+                "folder": {}
+            }),
+        ] {
+            let item: Item = serde_json::from_value(data).unwrap();
+            snapshot.state.upsert(item);
+        }
+        let mut writer = csv::Writer::from_writer(Vec::new());
+        write_inventory(
+            &mut writer,
+            "DRIVE",
+            &snapshot,
+            &ItemFilter::default(),
+            &Keeper::default(),
+        )
+        .unwrap();
+        let text = String::from_utf8(writer.into_inner().unwrap()).unwrap();
+        assert_eq!(
+            text,
+            concat!(
+                "drive_id,id,parent_path,name,type,size,sha1,quick_xor,duplicate\n",
+                "DRIVE,FOLDER,,Documents,folder,8192,,,\n",
+                "DRIVE,FILE,Documents,\"a, b.txt\",file,8192,9784E164A3626978D838EE21A0319C0DFB39001B,,\n",
+            )
+        );
+    }
+
+    #[test]
+    fn duplicate_actions() {
+        let mut snapshot = DriveSnapshot::default("https://graph.example.com", "DRIVE");
+        let item = |id: &str, parent: &str, sha: Option<&str>| {
+            let mut data = json!({
+                "id": id,
+                "name": id.rsplit('/').next().unwrap(),
+                "size": 8192,
+                "parentReference": {"id": parent, "driveType": "personal"},
+            });
+            match sha {
+                Some(sha) => data["file"] = json!({"hashes": {"sha1Hash": sha}}),
+                None => data["folder"] = json!({}),
+            }
+            data
+        };
+        let mut root = item("ROOT", "", None);
+        root["root"] = json!({});
+        root["parentReference"] = json!({"driveType": "personal"});
+        for data in [
+            root,
+            // two identical folders
+            item("A", "ROOT", None),
+            item("A/x.jpg", "A", Some("X")),
+            item("A/y.jpg", "A", Some("Y")),
+            item("B", "ROOT", None),
+            item("B/x.jpg", "B", Some("X")),
+            item("B/y.jpg", "B", Some("Y")),
+            // two identical files
+            item("c1.txt", "ROOT", Some("C")),
+            item("c2.txt", "ROOT", Some("C")),
+        ] {
+            let item: Item = serde_json::from_value(data).unwrap();
+            snapshot.state.upsert(item);
+        }
+        let mut writer = csv::Writer::from_writer(Vec::new());
+        write_inventory(
+            &mut writer,
+            "DRIVE",
+            &snapshot,
+            &ItemFilter::default(),
+            &Keeper::default(),
+        )
+        .unwrap();
+        let text = String::from_utf8(writer.into_inner().unwrap()).unwrap();
+        let actions = text
+            .lines()
+            .skip(1)
+            .map(|line| {
+                let fields = line.split(',').collect::<Vec<_>>();
+                (fields[1], fields[8])
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            actions,
+            [
+                ("A", "keep"),
+                ("B", "remove"),
+                ("c1.txt", "keep"),
+                ("c2.txt", "remove"),
+                ("A/x.jpg", "keep"),
+                ("A/y.jpg", "keep"),
+                ("B/x.jpg", "remove"),
+                ("B/y.jpg", "remove"),
+            ]
+        );
+    }
+}
@@ -1,5 +1,8 @@
+use crate::drive::{Drive, Identity};
+use crate::sync::DriveItemHandler;
+use chrono::{DateTime, Utc};
 use serde_derive::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Exists {
@@ -20,13 +23,48 @@ pub struct Hash {
 
 #[derive(Debug, Serialize, Deserialize, PartialEq)]
 pub struct Parent {
-    // Deleted parent may have no path
+    // The root folder has no parent
     #[serde(default, skip_serializing_if = "Option::is_none")]
-    pub path: Option<String>,
+    pub id: Option<String>,
     #[serde(rename = "driveType")]
     pub drive_type: String,
 }
 
+// Times recorded by the client that uploaded the file, which may be earlier than the upload
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FileSystemInfo {
+    #[serde(
+        rename = "createdDateTime",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub created: Option<String>,
+    #[serde(
+        rename = "lastModifiedDateTime",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub modified: Option<String>,
+}
+
+// The user or application that created or modified an item
+#[derive(Debug, Serialize, Deserialize)]
+pub struct IdentitySet {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user: Option<Identity>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub application: Option<Identity>,
+}
+
+impl IdentitySet {
+    pub fn name(&self) -> Option<&str> {
+        self.user
+            .iter()
+            .chain(&self.application)
+            .find_map(|identity| identity.display_name.as_deref())
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, PartialEq)]
 pub enum ItemType {
     #[serde(rename = "file")]
@@ -53,26 +91,139 @@ pub struct Item {
     pub item_type: ItemType,
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub deleted: Option<Exists>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub root: Option<Exists>,
+    // timestamps are ISO 8601 in UTC, with or without fractional seconds, so they do not sort by
+    // time as strings. Compare them with `parse_time`.
+    #[serde(
+        rename = "createdDateTime",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub created: Option<String>,
+    #[serde(
+        rename = "lastModifiedDateTime",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub modified: Option<String>,
+    #[serde(
+        rename = "fileSystemInfo",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub file_system: Option<FileSystemInfo>,
+    #[serde(rename = "createdBy", default, skip_serializing_if = "Option::is_none")]
+    pub created_by: Option<IdentitySet>,
+    #[serde(
+        rename = "lastModifiedBy",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub modified_by: Option<IdentitySet>,
 }
 
-#[derive(Serialize, Deserialize)]
+// Parse a timestamp returned by Graph, e.g. "2016-01-02T08:00:00.5Z"
+pub fn parse_time(time: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(time)
+        .ok()
+        .map(|time| time.with_timezone(&Utc))
+}
+
+impl Item {
+    // When the file was created, preferring the time on the client that uploaded it
+    pub fn created_time(&self) -> Option<&str> {
+        self.file_system
+            .as_ref()
+            .and_then(|info| info.created.as_deref())
+            .or(self.created.as_deref())
+    }
+
+    // When the file was last modified, preferring the time on the client that uploaded it
+    pub fn modified_time(&self) -> Option<&str> {
+        self.file_system
+            .as_ref()
+            .and_then(|info| info.modified.as_deref())
+            .or(self.modified.as_deref())
+    }
+}
+
+// The saved fields of a `DriveState`, from which the indexes are rebuilt
+#[derive(Deserialize)]
+struct SavedState {
+    size: u64,
+    items: HashMap<String, Item>,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+#[serde(from = "SavedState")]
 pub struct DriveState {
     pub size: u64,
     pub items: HashMap<String, Item>,
+    // IDs of the items in each folder
+    #[serde(skip)]
+    children: HashMap<String, HashSet<String>>,
+    #[serde(skip)]
+    root: Option<String>,
+}
+
+impl From<SavedState> for DriveState {
+    fn from(saved: SavedState) -> DriveState {
+        let mut state = DriveState {
+            size: saved.size,
+            ..DriveState::default()
+        };
+        for item in saved.items.values() {
+            state.link(item);
+        }
+        state.items = saved.items;
+        state
+    }
 }
 
 impl DriveState {
     pub fn reset(&mut self) -> u64 {
         self.size = 0;
         self.items.clear();
+        self.children.clear();
+        self.root = None;
         self.size
     }
 
+    // Add the item to the indexes
+    fn link(&mut self, item: &Item) {
+        if item.root.is_some() {
+            self.root = Some(item.id.clone());
+        }
+        if let Some(parent_id) = &item.parent.id {
+            self.children
+                .entry(parent_id.clone())
+                .or_default()
+                .insert(item.id.clone());
+        }
+    }
+
+    // Remove the item from the indexes
+    fn unlink(&mut self, item: &Item) {
+        if let Some(parent_id) = &item.parent.id {
+            if let Some(siblings) = self.children.get_mut(parent_id) {
+                siblings.remove(&item.id);
+                if siblings.is_empty() {
+                    self.children.remove(parent_id);
+                }
+            }
+        }
+    }
+
     pub fn upsert(&mut self, item: Item) -> u64 {
         if let ItemType::File { .. } = item.item_type {
             self.size += item.size;
         }
+        self.link(&item);
         if let Some(prev) = self.items.insert(item.id.clone(), item) {
+            if prev.parent.id != self.items[&prev.id].parent.id {
+                self.unlink(&prev);
+            }
             if let ItemType::File { .. } = prev.item_type {
                 let size = prev.size;
                 assert!(size <= self.size);
@@ -84,6 +235,7 @@ impl DriveState {
 
     pub fn delete(&mut self, item: Item) -> u64 {
         if let Some(prev) = self.items.remove(&item.id) {
+            self.unlink(&prev);
             if let ItemType::File { .. } = prev.item_type {
                 let size = prev.size;
                 assert!(size <= self.size);
@@ -92,22 +244,101 @@ impl DriveState {
         }
         self.size
     }
+
+    pub fn root(&self) -> Option<&Item> {
+        self.items.get(self.root.as_ref()?)
+    }
+
+    pub fn children<'a>(&'a self, item: &Item) -> impl Iterator<Item = &'a Item> {
+        self.children
+            .get(&item.id)
+            .into_iter()
+            .flatten()
+            .filter_map(move |id| self.items.get(id))
+    }
+
+    // The folders containing the item, from its parent up to the root
+    pub fn ancestors<'a>(&'a self, item: &'a Item) -> Ancestors<'a> {
+        Ancestors {
+            state: self,
+            next: item.parent.id.as_deref(),
+            // guard against a loop in the parent links
+            remaining: self.items.len(),
+        }
+    }
+
+    // Path of the folder containing the item, relative to the drive root. Returns `None` for
+    // the root, and for items not connected to the root, e.g. because the parent was deleted.
+    pub fn parent_path(&self, item: &Item) -> Option<String> {
+        let mut names = Vec::new();
+        for parent in self.ancestors(item) {
+            if parent.root.is_some() {
+                names.reverse();
+                return Some(names.join("/"));
+            }
+            names.push(parent.name.as_str());
+        }
+        None
+    }
+}
+
+pub struct Ancestors<'a> {
+    state: &'a DriveState,
+    next: Option<&'a str>,
+    remaining: usize,
+}
+
+impl<'a> Iterator for Ancestors<'a> {
+    type Item = &'a Item;
+
+    fn next(&mut self) -> Option<&'a Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        let item = self.state.items.get(self.next?)?;
+        self.next = item.parent.id.as_deref();
+        Some(item)
+    }
+}
+
+impl DriveItemHandler<Item> for DriveState {
+    fn reset(&mut self) {
+        DriveState::reset(self);
+    }
+
+    fn handle(&mut self, item: Item) {
+        if item.deleted.is_some() {
+            self.delete(item);
+        } else {
+            self.upsert(item);
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct DriveSnapshot {
     pub delta_link: String,
+    // Set while a sync is incomplete: the state includes all pages before this link
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub next_link: Option<String>,
+    // Drive details from the last sync, so that reports can be made offline
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub drive: Option<Drive>,
     #[serde(flatten)]
     pub state: DriveState,
 }
 
-pub fn initial_link(drive_id: &str) -> String {
-    const PREFIX: &str = "https://graph.microsoft.com/v1.0/me/drives/";
+pub fn initial_link(graph_url: &str, drive_id: &str) -> String {
+    const PREFIX: &str = "/me/drives/";
     const SUFFIX: &str = concat!(
         "/root/delta",
-        "?select=id,name,size,parentReference,file,folder,package,deleted"
+        "?select=id,name,size,parentReference,file,folder,package,deleted,root,",
+        "createdDateTime,lastModifiedDateTime,fileSystemInfo,createdBy,lastModifiedBy"
     );
-    let mut link = String::with_capacity(PREFIX.len() + drive_id.len() + SUFFIX.len());
+    let mut link =
+        String::with_capacity(graph_url.len() + PREFIX.len() + drive_id.len() + SUFFIX.len());
+    link.push_str(graph_url);
     link.push_str(PREFIX);
     link.push_str(drive_id);
     link.push_str(SUFFIX);
@@ -115,21 +346,20 @@ pub fn initial_link(drive_id: &str) -> String {
 }
 
 impl DriveSnapshot {
-    pub fn default(drive_id: &str) -> DriveSnapshot {
+    pub fn default(graph_url: &str, drive_id: &str) -> DriveSnapshot {
         // an initial state that will scan entire drive
         DriveSnapshot {
-            delta_link: initial_link(drive_id),
-            state: DriveState {
-                size: 0,
-                items: HashMap::new(),
-            },
+            delta_link: initial_link(graph_url, drive_id),
+            next_link: None,
+            drive: None,
+            state: DriveState::default(),
         }
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{Item, ItemType};
+    use super::{parse_time, DriveSnapshot, DriveState, Item, ItemType};
     use serde_json::json;
 
     #[test]
@@ -142,6 +372,14 @@ mod tests {
                 "path": "NAME",
                 "driveType": "personal"
             },
+            "createdDateTime": "2020-02-01T10:00:00Z",
+            "lastModifiedDateTime": "2020-02-01T10:00:00Z",
+            "fileSystemInfo": {
+                "createdDateTime": "2015-06-01T12:34:56Z",
+                "lastModifiedDateTime": "2016-01-02T08:00:00.5Z"
+            },
+            "createdBy": {"user": {"displayName": "Alice"}},
+            "lastModifiedBy": {"application": {"displayName": "OneDrive"}},
             "file": {
                 "hashes": {
                     "quickXorHash": "ZBIxs/4bmb5QuzTKkGJbU+7IsfM=",
@@ -154,6 +392,12 @@ mod tests {
         assert_eq!(item.id, "ID");
         assert_eq!(item.name, "NAME");
         assert_eq!(item.size, 8192);
+        assert_eq!(item.created_time(), Some("2015-06-01T12:34:56Z"));
+        assert_eq!(item.modified_time(), Some("2016-01-02T08:00:00.5Z"));
+        // later than the same second without a fraction, although it sorts earlier as a string
+        assert!(parse_time("2016-01-02T08:00:00.5Z") > parse_time("2016-01-02T08:00:00Z"));
+        assert_eq!(item.created_by.as_ref().unwrap().name(), Some("Alice"));
+        assert_eq!(item.modified_by.as_ref().unwrap().name(), Some("OneDrive"));
         match item.item_type {
             ItemType::File { .. } => {}
             _ => {
@@ -248,4 +492,59 @@ mod tests {
         }
         assert!(item.deleted.is_some());
     }
+
+    fn folder(id: &str, name: &str, parent: Option<&str>) -> Item {
+        let mut data = json!({
+            "id": id,
+            "name": name,
+            "parentReference": {
+                "id": parent,
+                "driveType": "personal"
+            },
+            "folder": {}
+        });
+        if parent.is_none() {
+            data["root"] = json!({});
+        }
+        serde_json::from_value(data).unwrap()
+    }
+
+    fn path(state: &DriveState, id: &str) -> Option<String> {
+        state.parent_path(&state.items[id])
+    }
+
+    #[test]
+    fn paths_follow_moves() {
+        let mut state = DriveState::default();
+        state.upsert(folder("ROOT", "root", None));
+        state.upsert(folder("A", "Pictures", Some("ROOT")));
+        state.upsert(folder("B", "2015", Some("A")));
+        state.upsert(folder("C", "Summer", Some("B")));
+        assert_eq!(path(&state, "ROOT"), None);
+        assert_eq!(path(&state, "A").as_deref(), Some(""));
+        assert_eq!(path(&state, "C").as_deref(), Some("Pictures/2015"));
+        // rename a folder
+        state.upsert(folder("A", "Family Photos", Some("ROOT")));
+        assert_eq!(path(&state, "C").as_deref(), Some("Family Photos/2015"));
+        // move a folder
+        state.upsert(folder("B", "2015", Some("ROOT")));
+        assert_eq!(path(&state, "C").as_deref(), Some("2015"));
+        assert_eq!(state.children(&state.items["A"]).count(), 0);
+        assert_eq!(state.children(&state.items["ROOT"]).count(), 2);
+        // delete a parent
+        state.delete(folder("B", "2015", Some("ROOT")));
+        assert_eq!(path(&state, "C"), None);
+    }
+
+    #[test]
+    fn saved_state_rebuilds_tree() {
+        let mut snapshot = DriveSnapshot::default("https://graph.example.com", "DRIVE");
+        snapshot.state.upsert(folder("ROOT", "root", None));
+        snapshot.state.upsert(folder("A", "Pictures", Some("ROOT")));
+        let data = serde_cbor::to_vec(&snapshot).unwrap();
+        let snapshot: DriveSnapshot = serde_cbor::from_slice(&data).unwrap();
+        let root = snapshot.state.root().unwrap();
+        assert_eq!(root.id, "ROOT");
+        assert_eq!(snapshot.state.children(root).count(), 1);
+    }
 }
@@ -0,0 +1,193 @@
+use crate::cli::{KeepPolicy, KeeperArgs};
+use crate::item::{parse_time, ItemType};
+use crate::size::FileRef;
+use std::cmp::{Ordering, Reverse};
+
+// Folder that phones upload photos to. Copies elsewhere have usually been sorted by hand.
+const CAMERA_ROLL: &str = "camera roll";
+
+// Chooses the copy of a duplicate file to keep
+#[derive(Default)]
+pub(crate) struct Keeper {
+    // tried in order until one copy is preferred
+    policies: Vec<KeepPolicy>,
+    // lowercase, relative to the drive root, most preferred first
+    preferred_folders: Vec<String>,
+}
+
+// What to do with a copy of a duplicate file, given its position after `Keeper::order`
+pub(crate) fn action(copy: usize) -> &'static str {
+    if copy == 0 {
+        "keep"
+    } else {
+        "remove"
+    }
+}
+
+// Position of the first preferred folder containing the path, or the number of folders if none do
+fn folder_rank(folders: &[String], path: &str) -> usize {
+    let path = path.to_lowercase();
+    folders
+        .iter()
+        .position(|folder| {
+            path.strip_prefix(folder.as_str())
+                .is_some_and(|rest| folder.is_empty() || rest.starts_with('/'))
+        })
+        .unwrap_or(folders.len())
+}
+
+// Whether a file, or a folder, is inside or is a camera roll folder
+fn in_camera_roll(path: &str, is_file: bool) -> bool {
+    let mut folders = path.split('/').rev().skip(usize::from(is_file));
+    folders.any(|folder| folder.eq_ignore_ascii_case(CAMERA_ROLL))
+}
+
+fn is_file(copy: &FileRef) -> bool {
+    matches!(copy.item.item_type, ItemType::File { .. })
+}
+
+// Order by time, with unknown times last
+fn oldest_first(a: Option<&str>, b: Option<&str>) -> Ordering {
+    let (a, b) = (a.and_then(parse_time), b.and_then(parse_time));
+    (a.is_none(), a).cmp(&(b.is_none(), b))
+}
+
+fn newest_first(a: Option<&str>, b: Option<&str>) -> Ordering {
+    let (a, b) = (a.and_then(parse_time), b.and_then(parse_time));
+    (a.is_none(), Reverse(a)).cmp(&(b.is_none(), Reverse(b)))
+}
+
+impl Keeper {
+    pub(crate) fn new(args: &KeeperArgs) -> Keeper {
+        let mut policies = args.policies.clone();
+        // preferring folders without saying how is a request to prefer them first
+        if !args.preferred_folders.is_empty() && !policies.contains(&KeepPolicy::PreferredFolder) {
+            policies.insert(0, KeepPolicy::PreferredFolder);
+        }
+        Keeper {
+            policies,
+            preferred_folders: args
+                .preferred_folders
+                .iter()
+                .map(|folder| folder.trim_matches('/').to_lowercase())
+                .collect(),
+        }
+    }
+
+    fn compare(&self, a: &FileRef, b: &FileRef) -> Ordering {
+        for policy in &self.policies {
+            let order = match policy {
+                KeepPolicy::ShortestPath => a.path.chars().count().cmp(&b.path.chars().count()),
+                KeepPolicy::Oldest => oldest_first(a.item.created_time(), b.item.created_time()),
+                KeepPolicy::Newest => newest_first(a.item.modified_time(), b.item.modified_time()),
+                KeepPolicy::PreferredFolder => folder_rank(&self.preferred_folders, &a.path)
+                    .cmp(&folder_rank(&self.preferred_folders, &b.path)),
+                KeepPolicy::NotCameraRoll => {
+                    in_camera_roll(&a.path, is_file(a)).cmp(&in_camera_roll(&b.path, is_file(b)))
+                }
+            };
+            if order != Ordering::Equal {
+                return order;
+            }
+        }
+        Ordering::Equal
+    }
+
+    // Put the copy to keep first. Copies that the policies do not choose between keep their
+    // order, so with no policies the first copy is kept.
+    pub(crate) fn order(&self, files: &mut [FileRef]) {
+        files.sort_by(|a, b| self.compare(a, b));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Keeper;
+    use crate::cli::{KeepPolicy, KeeperArgs};
+    use crate::item::Item;
+    use crate::size::FileRef;
+    use serde_json::json;
+
+    #[test]
+    fn keeper_policies() {
+        let item = |id: &str, created: Option<&str>, modified: &str| -> Item {
+            let mut data = json!({
+                "id": id,
+                "name": id,
+                "parentReference": {"id": "ROOT", "driveType": "personal"},
+                "file": {},
+                "lastModifiedDateTime": modified,
+            });
+            if let Some(created) = created {
+                data["createdDateTime"] = json!(created);
+            }
+            serde_json::from_value(data).unwrap()
+        };
+        let items = [
+            item("a", Some("2015-01-01T00:00:00Z"), "2015-01-01T00:00:00Z"),
+            item("b", Some("2014-01-01T00:00:00Z"), "2016-01-01T00:00:00Z"),
+            item("c", None, "2017-01-01T00:00:00Z"),
+        ];
+        let paths = [
+            "Pictures/Camera Roll/IMG_1.jpg",
+            "Pictures/Sorted/Holiday/IMG_1.jpg",
+            "Backup/IMG_1.jpg",
+        ];
+        let keep = |policies: &[KeepPolicy], preferred_folders: &[&str]| {
+            let keeper = Keeper::new(&KeeperArgs {
+                policies: policies.to_vec(),
+                preferred_folders: preferred_folders.iter().map(|f| f.to_string()).collect(),
+            });
+            let mut files = items
+                .iter()
+                .zip(paths)
+                .map(|(item, path)| FileRef {
+                    item,
+                    path: path.to_owned(),
+                })
+                .collect::<Vec<_>>();
+            keeper.order(&mut files);
+            files[0].item.id.clone()
+        };
+        assert_eq!(keep(&[], &[]), "a");
+        assert_eq!(keep(&[KeepPolicy::ShortestPath], &[]), "c");
+        assert_eq!(keep(&[KeepPolicy::Oldest], &[]), "b");
+        assert_eq!(keep(&[KeepPolicy::Newest], &[]), "c");
+        // times with and without fractions of a second
+        let times = [
+            "2016-01-02T08:00:00Z",
+            "2016-01-02T08:00:00.5Z",
+            "2016-01-02T07:59:59.999Z",
+        ];
+        let ordered = |policy: KeepPolicy| {
+            let keeper = Keeper::new(&KeeperArgs {
+                policies: vec![policy],
+                preferred_folders: Vec::new(),
+            });
+            let items = times
+                .iter()
+                .map(|time| item(time, Some(time), time))
+                .collect::<Vec<_>>();
+            let mut files = items
+                .iter()
+                .map(|item| FileRef {
+                    item,
+                    path: item.id.clone(),
+                })
+                .collect::<Vec<_>>();
+            keeper.order(&mut files);
+            files[0].item.id.clone()
+        };
+        assert_eq!(ordered(KeepPolicy::Oldest), times[2]);
+        assert_eq!(ordered(KeepPolicy::Newest), times[1]);
+        assert_eq!(keep(&[], &["backup", "pictures/sorted"]), "c");
+        assert_eq!(keep(&[], &["/Pictures/"]), "a");
+        assert_eq!(
+            keep(
+                &[KeepPolicy::PreferredFolder, KeepPolicy::NotCameraRoll],
+                &["Pictures"]
+            ),
+            "b"
+        );
+    }
+}
@@ -1,5 +1,21 @@
 mod auth;
+mod browse;
+mod cache;
+mod cli;
+mod compare;
+mod config;
+mod drive;
+#[cfg(test)]
+mod fake_graph;
+mod filter;
+mod folders;
+mod html;
+mod interrupt;
+mod inventory;
 mod item;
+mod keeper;
+mod quickxor;
+mod report;
 mod size;
 mod storage;
 mod sync;
@@ -15,78 +31,112 @@ mod sync;
 #[global_allocator]
 static ALLOC: jemallocator::Jemalloc = jemallocator::Jemalloc;
 
-use crate::item::{initial_link, DriveSnapshot, DriveState, Item};
-use crate::size::{bucket_by_size, size_as_string};
+use crate::auth::LoginMethod;
+use crate::cache::{age_as_string, cache_filename, info_filename, token_filename};
+use crate::cli::{CacheAction, Cli, Command};
+use crate::config::{config_filename, ignore_filename, AuthConfig, Config, ConfigFile};
+use crate::drive::{Drive, DriveFilter, DriveList};
+use crate::filter::ItemFilter;
+use crate::item::{initial_link, DriveSnapshot, Item};
+use crate::keeper::Keeper;
+use crate::report::Reporter;
+use crate::size::size_as_string;
 use crate::storage::Storage;
-use crate::sync::{sync_drive_items, DriveItemHandler};
-use eyre::{Report, Result, bail, ensure};
+use crate::sync::{sync_drive_items, DriveItemHandler, SyncError};
+use clap::Parser;
+use eyre::{bail, ensure, Report, Result};
 use oauth2::basic::BasicTokenType;
 use oauth2::TokenResponse;
 use reqwest::blocking::Client;
 use reqwest::{header, StatusCode};
-use serde_json::Value;
-use std::time::Duration;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
 
 const CRATE_NAME: Option<&str> = option_env!("CARGO_PKG_NAME");
 const CRATE_VERSION: Option<&str> = option_env!("CARGO_PKG_VERSION");
 
-// To replace this client ID, register a Public client/native application in Azure Active Directory.
-// See https://docs.microsoft.com/azure/active-directory/develop/quickstart-register-app
-// Under `Authentication` set the redirect URI to `http://localhost/redirect` and enable `Allow public client flows`.
-// Under `API permissions` add Microsoft Graph delegated permission `Files.Read.All`.
-// Add the `Application (client) ID` as the `CLIENT_ID` below.
-const CLIENT_ID: &str = "3a139972-0147-433a-9ab8-faa3dd1b9eb5";
-
-fn cache_filename(project: &directories::ProjectDirs, drive_id: &str) -> std::path::PathBuf {
-    let mut cache_path = project.cache_dir().to_path_buf();
-    if let Err(_) = std::fs::create_dir_all(&cache_path) {
-        // let a later error sort it out
-    }
-    // Increment the number after `drive` when the serialized format changes.
-    // 2021-05-23 - updated to 2 because the original delta link format is no longer valid
-    // 2021-06-05 - remove mime type from saved data
-    cache_path.push(format!("drive3_{}", drive_id));
-    cache_path.set_extension("cbor");
-    cache_path
-}
+// Time between saves of the partial drive state during a sync
+const CHECKPOINT_INTERVAL: Duration = Duration::from_secs(60);
 
 struct ItemHandler<'a> {
-    state: &'a mut DriveState,
+    snapshot: &'a mut DriveSnapshot,
+    cache: &'a Storage<DriveSnapshot>,
     bar: &'a indicatif::ProgressBar,
+    saved: Instant,
 }
 
 impl<'a> DriveItemHandler<Item> for ItemHandler<'a> {
     fn reset(&mut self) {
-        let size = self.state.reset();
-        self.bar.set_position(size);
+        self.snapshot.state.reset();
+        self.snapshot.next_link = None;
+        self.bar.set_position(self.snapshot.state.size);
     }
 
     fn handle(&mut self, item: Item) {
-        let size = if item.deleted.is_some() {
-            self.state.delete(item)
-        } else {
-            self.state.upsert(item)
-        };
-        self.bar.set_position(size);
+        self.snapshot.state.handle(item);
+        self.bar.set_position(self.snapshot.state.size);
+    }
+
+    fn checkpoint(&mut self, next_link: &str) {
+        self.snapshot.next_link = Some(next_link.to_owned());
+        if self.saved.elapsed() >= CHECKPOINT_INTERVAL {
+            if let Err(err) = self.cache.save(self.snapshot) {
+                eprintln!("Error saving cache: {}", err);
+            }
+            self.saved = Instant::now();
+        }
     }
 }
 
 fn sync_items(
     client: &Client,
-    mut snapshot: DriveSnapshot,
+    snapshot: &mut DriveSnapshot,
+    cache: &Storage<DriveSnapshot>,
     reset_link: String,
     bar: &indicatif::ProgressBar,
-) -> Result<DriveSnapshot> {
+) -> Result<()> {
+    // continue an interrupted sync from the last page received
+    let link = match &snapshot.next_link {
+        Some(next_link) => next_link.clone(),
+        None => snapshot.delta_link.clone(),
+    };
     let mut handler = ItemHandler {
-        state: &mut snapshot.state,
+        snapshot,
+        cache,
         bar,
+        saved: Instant::now(),
     };
-    snapshot.delta_link = sync_drive_items(client, reset_link, snapshot.delta_link, &mut handler)?;
-    Ok(snapshot)
+    let _syncing = interrupt::Syncing::start();
+    match sync_drive_items(client, reset_link, link, &mut handler, &interrupt::STOP) {
+        Ok(delta_link) => {
+            snapshot.delta_link = delta_link;
+            snapshot.next_link = None;
+            Ok(())
+        }
+        Err(error) => {
+            // keep the pages received so far for the next run
+            if snapshot.next_link.is_some() {
+                if let Err(err) = cache.save(snapshot) {
+                    eprintln!("Error saving cache: {}", err);
+                }
+            }
+            Err(error.into())
+        }
+    }
 }
 
-fn get_msgraph_client() -> Result<Client> {
-    let token = auth::authenticate(CLIENT_ID.to_owned())?;
+fn get_msgraph_client(
+    config: &AuthConfig,
+    login: LoginMethod,
+    cache_dir: &Option<PathBuf>,
+) -> Result<Client> {
+    let token_cache = Storage::new(
+        cache_dir
+            .as_ref()
+            .map(|dir| token_filename(dir, &config.client_id, &config.tenant)),
+    );
+    let token = auth::authenticate(config, login, &token_cache)?;
     let mut headers = header::HeaderMap::new();
     headers.insert(
         header::USER_AGENT,
@@ -102,7 +152,7 @@ fn get_msgraph_client() -> Result<Client> {
                 header::AUTHORIZATION,
                 header::HeaderValue::from_str(&format!(
                     "Bearer {}",
-                    token.access_token().secret().to_string()
+                    token.access_token().secret()
                 ))?,
             );
         }
@@ -118,11 +168,13 @@ fn get_msgraph_client() -> Result<Client> {
 }
 
 fn fetch_drive(
-    drive_id: &str,
-    expected: u64,
-    project_dirs: &Option<directories::ProjectDirs>,
+    drive: &Drive,
+    graph_url: &str,
+    cache_dir: &Option<PathBuf>,
     client: &Client,
 ) -> Result<DriveSnapshot> {
+    let drive_id = drive.id.as_str();
+    let expected = drive.quota.as_ref().map_or(0, |quota| quota.used);
     let bar = indicatif::ProgressBar::new(expected);
     bar.set_style(
         indicatif::ProgressStyle::default_bar()
@@ -130,63 +182,108 @@ fn fetch_drive(
             .progress_chars("#>-"),
     );
     bar.enable_steady_tick(100);
-    let cache = Storage::new(
-        project_dirs
-            .as_ref()
-            .map(|dir| cache_filename(dir, drive_id)),
-    );
-    let snapshot = cache
+    let cache = Storage::new(cache_dir.as_ref().map(|dir| cache_filename(dir, drive_id)));
+    let mut snapshot = cache
         .load()
-        .unwrap_or_else(|| DriveSnapshot::default(drive_id));
+        .unwrap_or_else(|| DriveSnapshot::default(graph_url, drive_id));
+    snapshot.drive = Some(drive.clone());
+    let info = Storage::new(cache_dir.as_ref().map(|dir| info_filename(dir, drive_id)));
+    if let Err(err) = info.save(drive) {
+        eprintln!("Error saving cache: {}", err);
+    }
+    if snapshot.next_link.is_some() {
+        bar.println(format!("Resuming interrupted sync of drive {}", drive_id));
+    }
     bar.set_position(snapshot.state.size);
-    let snapshot = sync_items(client, snapshot, initial_link(drive_id), &bar)?;
+    let result = sync_items(
+        client,
+        &mut snapshot,
+        &cache,
+        initial_link(graph_url, drive_id),
+        &bar,
+    );
     bar.finish_and_clear();
+    result?;
     if let Err(err) = cache.save(&snapshot) {
         eprintln!("Error saving cache: {}", err);
     }
     Ok(snapshot)
 }
 
-fn show_usage(drive: &Value) {
-    let quota = &drive["quota"];
-    let total = quota["total"].as_u64().unwrap();
-    let used = quota["used"].as_u64().unwrap();
-    let deleted = quota["deleted"].as_u64().unwrap();
-    let remaining = quota["remaining"].as_u64().unwrap();
-    assert!(used + remaining == total);
-    println!("total:  {:>18}", size_as_string(total));
-    println!("free:   {:>18}", size_as_string(remaining));
-    println!(
-        "used:   {:>18} = {:.2}% (including {} pending deletion)",
-        size_as_string(used),
-        used as f32 * 100.0 / total as f32,
-        size_as_string(deleted)
-    );
+// The cached data for each drive, with its age
+fn load_cached_drives(
+    cache_dir: &Option<PathBuf>,
+    filter: &DriveFilter,
+) -> Result<Vec<(Drive, DriveSnapshot, Option<Duration>)>> {
+    let cache_dir = match cache_dir {
+        Some(cache_dir) => cache_dir,
+        None => bail!("No cache directory available"),
+    };
+    let mut drives = Vec::new();
+    for cached in cache::cached_drives(cache_dir)? {
+        // skip unselected drives before loading their data, if the drive details were saved
+        let info: Option<Drive> = Storage::new(Some(cached.info_path.clone())).load();
+        if matches!(&info, Some(drive) if !filter.matches(drive)) {
+            continue;
+        }
+        let snapshot: DriveSnapshot = match Storage::new(Some(cached.path.clone())).load() {
+            Some(snapshot) => snapshot,
+            None => continue,
+        };
+        let drive = snapshot
+            .drive
+            .clone()
+            .or(info)
+            .unwrap_or_else(|| Drive::unknown(&cached.drive_id));
+        if filter.matches(&drive) {
+            eprintln!(
+                "Using cached data for drive {} from {} ago",
+                drive.id,
+                age_as_string(cached.age())
+            );
+            if snapshot.next_link.is_some() {
+                eprintln!(
+                    "Cached data for drive {} is from an incomplete sync",
+                    drive.id
+                );
+            }
+            drives.push((drive, snapshot, cached.age()));
+        }
+    }
+    Ok(drives)
 }
 
-fn show_duplicates(snapshot: DriveSnapshot) {
-    let (file_count, folder_count, names_by_hash_by_size) = bucket_by_size(&snapshot.state.items);
-    println!("folders:{:>10}", folder_count);
-    println!("files:  {:>10}", file_count);
-    println!("duplicates:");
-    for (size, names_by_hash) in names_by_hash_by_size.iter().rev() {
-        for names in names_by_hash.values() {
-            if names.len() > 1 {
-                println!("{}", size_as_string(*size));
-                for name in names {
-                    println!("\t{}", name);
-                }
-            }
+// Drive data is either fetched from Microsoft Graph, updating the cache, or read only from the
+// cache without signing in.
+enum Source {
+    Online {
+        client: Client,
+        graph_url: String,
+        cache_dir: Option<PathBuf>,
+    },
+    Offline {
+        snapshots: HashMap<String, DriveSnapshot>,
+    },
+}
+
+impl Source {
+    fn snapshot(&mut self, drive: &Drive) -> Result<DriveSnapshot> {
+        match self {
+            Source::Online {
+                client,
+                graph_url,
+                cache_dir,
+            } => fetch_drive(drive, graph_url, cache_dir, client),
+            Source::Offline { snapshots } => match snapshots.remove(&drive.id) {
+                Some(snapshot) => Ok(snapshot),
+                None => bail!("No cached data for drive {}", drive.id),
+            },
         }
     }
 }
 
-fn main() -> Result<()> {
-    let project_dirs = directories::ProjectDirs::from("Casa", "Giddy", "MSOD-stat");
-    let client = get_msgraph_client()?;
-    let response = client
-        .get("https://graph.microsoft.com/v1.0/me/drives")
-        .send()?;
+fn list_drives(client: &Client, graph_url: &str, filter: &DriveFilter) -> Result<Vec<Drive>> {
+    let response = client.get(format!("{}/me/drives", graph_url)).send()?;
     ensure!(
         response.status() == StatusCode::OK,
         "{:?} {}",
@@ -194,19 +291,203 @@ fn main() -> Result<()> {
         response.status().canonical_reason().unwrap()
     );
     let result = response.text()?;
-    let json: Value = serde_json::from_str(&result)?;
-    for drive in json["value"].as_array().unwrap() {
-        let drive_id = drive["id"].as_str().unwrap();
-        println!();
-        println!("Drive {}", drive_id);
-        show_usage(drive);
-        let snapshot = fetch_drive(
-            drive_id,
-            drive["quota"]["used"].as_u64().unwrap(),
-            &project_dirs,
-            &client,
-        )?;
-        show_duplicates(snapshot);
+    let drives: DriveList = serde_json::from_str(&result)?;
+    Ok(drives
+        .value
+        .into_iter()
+        .filter(|drive| filter.matches(drive))
+        .collect())
+}
+
+fn manage_cache(
+    action: CacheAction,
+    drive_ids: &[String],
+    cache_dir: Option<PathBuf>,
+) -> Result<()> {
+    let cache_dir = match cache_dir {
+        Some(cache_dir) => cache_dir,
+        None => bail!("No cache directory available"),
+    };
+    match action {
+        CacheAction::Path => {
+            println!("{}", cache_dir.display());
+        }
+        CacheAction::List => {
+            for cached in cache::cached_drives(&cache_dir)? {
+                println!(
+                    "{}  {:>12} old  {:>18}",
+                    cached.drive_id,
+                    age_as_string(cached.age()),
+                    size_as_string(cached.size)
+                );
+            }
+        }
+        CacheAction::Clear => {
+            for cached in cache::cached_drives(&cache_dir)? {
+                if drive_ids.is_empty() || drive_ids.contains(&cached.drive_id) {
+                    std::fs::remove_file(&cached.path)?;
+                    if cached.info_path.exists() {
+                        std::fs::remove_file(&cached.info_path)?;
+                    }
+                }
+            }
+            for (drive_id, path) in cache::stale_drives(&cache_dir)? {
+                if drive_ids.is_empty() || drive_ids.contains(&drive_id) {
+                    std::fs::remove_file(&path)?;
+                }
+            }
+            if drive_ids.is_empty() {
+                for path in cache::cached_tokens(&cache_dir)? {
+                    std::fs::remove_file(&path)?;
+                }
+            }
+        }
     }
     Ok(())
 }
+
+fn main() -> Result<()> {
+    interrupt::install()?;
+    let result = run();
+    if let Err(error) = &result {
+        if let Some(SyncError::Interrupted) = error.downcast_ref() {
+            eprintln!("Sync interrupted, run again to continue");
+            std::process::exit(interrupt::EXIT_STATUS);
+        }
+    }
+    result
+}
+
+fn run() -> Result<()> {
+    let cli = Cli::parse();
+    let project_dirs = directories::ProjectDirs::from("Casa", "Giddy", "MSOD-stat");
+    let cache_dir = cache::cache_dir(cli.cache_dir, &project_dirs);
+    if let Some(Command::Cache { action }) = cli.command {
+        return manage_cache(action, &cli.drives, cache_dir);
+    }
+    let filter = DriveFilter {
+        drives: cli.drives,
+        drive_types: cli.drive_types,
+    };
+    let item_filter = ItemFilter::new(
+        &cli.filter,
+        ignore_filename(cli.config.as_deref(), &project_dirs).as_deref(),
+    )?;
+    let keeper = Keeper::new(&cli.keeper);
+    if let Some(Command::Browse) = cli.command {
+        // browse the cached data, so that it opens without signing in or waiting for a sync
+        let cached = load_cached_drives(&cache_dir, &filter)?;
+        if cached.is_empty() {
+            eprintln!("No cached drive data, run `msod-stat sync` first");
+        }
+        for (drive, snapshot, age) in &cached {
+            browse::browse(drive, snapshot, &item_filter, &keeper, *age)?;
+        }
+        return Ok(());
+    }
+    let (drives, mut source) = if cli.offline {
+        ensure!(
+            !matches!(cli.command, Some(Command::Sync)),
+            "Cannot sync drives when offline"
+        );
+        let cached = load_cached_drives(&cache_dir, &filter)?;
+        let drives = cached.iter().map(|(drive, _, _)| drive.clone()).collect();
+        let snapshots = cached
+            .into_iter()
+            .map(|(drive, snapshot, _)| (drive.id, snapshot))
+            .collect();
+        (drives, Source::Offline { snapshots })
+    } else {
+        let login = if cli.sign_in.device_code {
+            LoginMethod::DeviceCode
+        } else {
+            LoginMethod::Browser
+        };
+        let config_file = match config_filename(cli.config, &project_dirs) {
+            Some(path) => ConfigFile::load(&path)?,
+            None => ConfigFile::default(),
+        };
+        let config = Config::new(cli.graph_url, cli.sign_in, config_file);
+        let client = get_msgraph_client(&config.auth, login, &cache_dir)?;
+        let drives = list_drives(&client, &config.graph_url, &filter)?;
+        let source = Source::Online {
+            client,
+            graph_url: config.graph_url,
+            cache_dir,
+        };
+        (drives, source)
+    };
+    if let Some(Command::Inventory { output }) = &cli.command {
+        let mut writer = inventory::writer(output.as_deref())?;
+        for drive in &drives {
+            let snapshot = source.snapshot(drive)?;
+            inventory::write_inventory(&mut writer, &drive.id, &snapshot, &item_filter, &keeper)?;
+        }
+        writer.flush()?;
+        return Ok(());
+    }
+    if let Some(Command::Html { output }) = &cli.command {
+        let mut sections = String::new();
+        for drive in &drives {
+            let snapshot = source.snapshot(drive)?;
+            html::drive_section(&mut sections, drive, &snapshot, &item_filter, &keeper);
+        }
+        std::fs::write(output, html::document(&sections))?;
+        return Ok(());
+    }
+    let mut reporter = Reporter::new(cli.format);
+    if let Some(Command::ListDrives) = cli.command {
+        for drive in &drives {
+            reporter.list_drive(drive);
+        }
+        return reporter.finish();
+    }
+    for drive in &drives {
+        reporter.drive(drive);
+        match cli.command {
+            None => {
+                reporter.usage(drive);
+                let snapshot = source.snapshot(drive)?;
+                reporter.duplicates(&snapshot, &item_filter, &keeper);
+            }
+            Some(Command::Usage) => {
+                reporter.usage(drive);
+            }
+            Some(Command::Sync) => {
+                source.snapshot(drive)?;
+            }
+            Some(Command::Duplicates) => {
+                let snapshot = source.snapshot(drive)?;
+                reporter.duplicates(&snapshot, &item_filter, &keeper);
+            }
+            Some(Command::Largest {
+                count,
+                min_depth,
+                max_depth,
+            }) => {
+                let snapshot = source.snapshot(drive)?;
+                let depths = min_depth..=max_depth.unwrap_or(usize::MAX);
+                reporter.largest(&snapshot, count, &depths);
+            }
+            Some(Command::Tree { depth }) => {
+                let snapshot = source.snapshot(drive)?;
+                reporter.tree(&snapshot, depth)?;
+            }
+            Some(Command::Compare {
+                ref local,
+                ref drive_folder,
+            }) => {
+                let snapshot = source.snapshot(drive)?;
+                let comparison =
+                    compare::compare(&snapshot.state, drive_folder, local, &item_filter)?;
+                reporter.comparison(&comparison);
+            }
+            Some(Command::ListDrives)
+            | Some(Command::Browse)
+            | Some(Command::Inventory { .. })
+            | Some(Command::Html { .. })
+            | Some(Command::Cache { .. }) => unreachable!(),
+        }
+    }
+    reporter.finish()
+}
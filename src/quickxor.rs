@@ -0,0 +1,80 @@
+// The QuickXorHash used by OneDrive for Business and SharePoint to identify file contents.
+// Each byte of the file is XORed into a 160-bit circular buffer, 11 bits further along than the
+// previous byte, and the file length is XORed into the last 64 bits.
+// https://learn.microsoft.com/en-us/onedrive/developer/code-snippets/quickxorhash
+
+const WIDTH_IN_BITS: usize = 160;
+const WIDTH_IN_BYTES: usize = WIDTH_IN_BITS / 8;
+const SHIFT: usize = 11;
+
+pub(crate) struct QuickXorHash {
+    data: [u8; WIDTH_IN_BYTES],
+    // bit position at which the next byte is XORed
+    shift: usize,
+    length: u64,
+}
+
+impl QuickXorHash {
+    pub(crate) fn new() -> QuickXorHash {
+        QuickXorHash {
+            data: [0; WIDTH_IN_BYTES],
+            shift: 0,
+            length: 0,
+        }
+    }
+
+    pub(crate) fn update(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            let index = self.shift / 8;
+            let value = u16::from(byte) << (self.shift % 8);
+            self.data[index] ^= value as u8;
+            self.data[(index + 1) % WIDTH_IN_BYTES] ^= (value >> 8) as u8;
+            self.shift = (self.shift + SHIFT) % WIDTH_IN_BITS;
+        }
+        self.length += bytes.len() as u64;
+    }
+
+    // The hash in base64, as returned by Graph in `quickXorHash`
+    pub(crate) fn finish(mut self) -> String {
+        let length = self.length.to_le_bytes();
+        for (byte, length) in self.data[WIDTH_IN_BYTES - length.len()..]
+            .iter_mut()
+            .zip(length)
+        {
+            *byte ^= length;
+        }
+        base64::encode(self.data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::QuickXorHash;
+
+    fn hash(chunks: &[&[u8]]) -> String {
+        let mut hasher = QuickXorHash::new();
+        for chunk in chunks {
+            hasher.update(chunk);
+        }
+        hasher.finish()
+    }
+
+    #[test]
+    fn known_hashes() {
+        assert_eq!(hash(&[]), "AAAAAAAAAAAAAAAAAAAAAAAAAAA=");
+        assert_eq!(hash(&[b"hello world"]), "aCgDG9jwBhDc4Q1yawMZAAAAAAA=");
+        assert_eq!(
+            hash(&[b"The quick brown fox ", b"jumps over the lazy dog"]),
+            "bMSlbysmxJL6S75XwfMcQZOpcr4="
+        );
+        // longer than the buffer, so that the shift wraps around
+        let data = (0..1000)
+            .map(|i| ((i * 7 + 3) % 251) as u8)
+            .collect::<Vec<_>>();
+        assert_eq!(hash(&[&data]), "XwCwWw+SPjp9D8+NCRe+PKCPYvw=");
+        assert_eq!(
+            hash(&[&data[..333], &data[333..]]),
+            "XwCwWw+SPjp9D8+NCRe+PKCPYvw="
+        );
+    }
+}
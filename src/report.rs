@@ -0,0 +1,808 @@
+use crate::cli::Format;
+use crate::compare::Comparison;
+use crate::drive::{Drive, Quota};
+use crate::filter::ItemFilter;
+use crate::folders::{duplicate_folders, DuplicateFolders};
+use crate::item::{parse_time, DriveSnapshot, DriveState, IdentitySet, Item, ItemType};
+use crate::keeper::{action, Keeper};
+use crate::size::{
+    bucket_by_size, item_hashes, join_path, size_as_string, wasted, Buckets, FileRef, ItemHash,
+};
+use eyre::Result;
+use serde_derive::Serialize;
+use std::collections::BTreeMap;
+use std::ops::RangeInclusive;
+
+pub fn show_drive(drive: &Drive) {
+    println!(
+        "{}  {:<15}  {}  ({})",
+        drive.id,
+        drive.drive_type,
+        drive.name,
+        drive.owner_name().unwrap_or("unknown owner")
+    );
+}
+
+pub fn show_usage(quota: &Quota) {
+    let total = quota.total;
+    let used = quota.used;
+    let deleted = quota.deleted;
+    let remaining = quota.remaining;
+    assert!(used + remaining == total);
+    println!("total:  {:>18}", size_as_string(total));
+    println!("free:   {:>18}", size_as_string(remaining));
+    println!(
+        "used:   {:>18} = {:.2}% (including {} pending deletion)",
+        size_as_string(used),
+        used as f32 * 100.0 / total as f32,
+        size_as_string(deleted)
+    );
+}
+
+// Date and time of an ISO 8601 timestamp, without fractions of a second
+fn timestamp_as_string(timestamp: &str) -> String {
+    match parse_time(timestamp) {
+        Some(time) => time.format("%Y-%m-%d %H:%M:%S").to_string(),
+        None => timestamp.to_owned(),
+    }
+}
+
+// When and by whom an item was created and last modified, as far as they are known
+pub(crate) fn history_as_string(item: &Item) -> String {
+    let change = |action: &str, time: Option<&str>, by: &Option<IdentitySet>| {
+        let by = by.as_ref().and_then(IdentitySet::name);
+        match (time, by) {
+            (None, None) => None,
+            (Some(time), None) => Some(format!("{} {}", action, timestamp_as_string(time))),
+            (None, Some(by)) => Some(format!("{} by {}", action, by)),
+            (Some(time), Some(by)) => Some(format!(
+                "{} {} by {}",
+                action,
+                timestamp_as_string(time),
+                by
+            )),
+        }
+    };
+    let changes = [
+        change("created", item.created_time(), &item.created_by),
+        change("modified", item.modified_time(), &item.modified_by),
+    ];
+    changes
+        .iter()
+        .flatten()
+        .cloned()
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+pub fn similarity_as_string(similarity: f64) -> String {
+    if similarity >= 1.0 {
+        "identical".to_owned()
+    } else {
+        // round down so that near-identical folders never show as 100%
+        format!("{}% similar", (similarity * 100.0).floor())
+    }
+}
+
+pub fn show_duplicates(buckets: &Buckets, folders: &DuplicateFolders) {
+    println!("folders:{:>10}", buckets.folder_count);
+    println!("files:  {:>10}", buckets.file_count);
+    println!("duplicate folders:");
+    for group in &folders.groups {
+        println!(
+            "{} ({}), {} reclaimable",
+            size_as_string(group.size),
+            similarity_as_string(group.similarity),
+            size_as_string(group.wasted)
+        );
+        for (copy, folder) in group.folders.iter().enumerate() {
+            println!("\t{:<6}  {}/", action(copy), folder.path);
+        }
+    }
+    println!("duplicates:");
+    for (size, _, files) in buckets
+        .duplicates()
+        .filter(|(_, _, files)| !folders.covers(files))
+    {
+        println!(
+            "{} x {}, {} reclaimable",
+            size_as_string(size),
+            files.len(),
+            size_as_string(wasted(size, files.len()))
+        );
+        for (copy, file) in files.iter().enumerate() {
+            let history = history_as_string(file.item);
+            if history.is_empty() {
+                println!("\t{:<6}  {}", action(copy), file.path);
+            } else {
+                println!("\t{:<6}  {}  ({})", action(copy), file.path, history);
+            }
+        }
+    }
+    if buckets.probable_duplicates().next().is_some() {
+        println!("probable duplicates (same size and name, contents not compared):");
+        for (size, files) in buckets.probable_duplicates() {
+            println!("{} x {}", size_as_string(size), files.len());
+            for file in files {
+                println!("\t{}", file.path);
+            }
+        }
+    }
+    if !buckets.mismatches.is_empty() {
+        println!("hash mismatches (copies that may be corrupted):");
+        for (size, files) in &buckets.mismatches {
+            println!("{} x {}", size_as_string(*size), files.len());
+            for file in files {
+                let hashes = item_hashes(file.item)
+                    .iter()
+                    .map(|hash| format!("{} {}", hash.kind(), hash.value()))
+                    .collect::<Vec<_>>();
+                println!("\t{}  ({})", file.path, hashes.join(", "));
+            }
+        }
+    }
+    let reclaimable = buckets.reclaimable();
+    println!("reclaimable: {:>18}", size_as_string(reclaimable.total));
+    for (folder, wasted) in reclaimable.by_folder {
+        println!("\t{:>18}  {}", size_as_string(wasted), folder);
+    }
+}
+
+// Number of levels below the drive root, e.g. 1 for a top-level folder or a file at the root
+fn depth(path: &str) -> usize {
+    path.split('/').count()
+}
+
+fn largest_files<'a>(
+    snapshot: &'a DriveSnapshot,
+    count: usize,
+    depths: &RangeInclusive<usize>,
+) -> Vec<FileRef<'a>> {
+    let mut files = snapshot
+        .state
+        .items
+        .values()
+        .filter(|item| matches!(item.item_type, ItemType::File { .. }))
+        .filter_map(|item| {
+            let dirname = snapshot.state.parent_path(item)?;
+            Some((item.size, dirname, item))
+        })
+        .filter(|(_, dirname, item)| depths.contains(&depth(&join_path(dirname, &item.name))))
+        .collect::<Vec<_>>();
+    files.sort_unstable_by(|a, b| (b.0, &b.1, &b.2.name).cmp(&(a.0, &a.1, &a.2.name)));
+    files
+        .into_iter()
+        .take(count)
+        .map(|(_, dirname, item)| FileRef {
+            item,
+            path: join_path(&dirname, &item.name),
+        })
+        .collect()
+}
+
+// Add the folders below `folder` to `folders`, with the total size of their files and
+// subfolders, and return the total size of `folder`
+fn folder_sizes<'a>(
+    state: &'a DriveState,
+    folder: &'a Item,
+    path: &str,
+    folders: &mut Vec<(u64, FileRef<'a>)>,
+) -> u64 {
+    let mut size = 0;
+    for child in state.children(folder) {
+        match child.item_type {
+            ItemType::File { .. } => size += child.size,
+            ItemType::Folder {} | ItemType::Package {} => {
+                let child_path = join_path(path, &child.name);
+                let child_size = folder_sizes(state, child, &child_path, folders);
+                size += child_size;
+                folders.push((
+                    child_size,
+                    FileRef {
+                        item: child,
+                        path: child_path,
+                    },
+                ));
+            }
+        }
+    }
+    size
+}
+
+fn largest_folders<'a>(
+    snapshot: &'a DriveSnapshot,
+    count: usize,
+    depths: &RangeInclusive<usize>,
+) -> Vec<(u64, FileRef<'a>)> {
+    let mut folders = Vec::new();
+    if let Some(root) = snapshot.state.root() {
+        folder_sizes(&snapshot.state, root, "", &mut folders);
+    }
+    folders.retain(|(_, folder)| depths.contains(&depth(&folder.path)));
+    folders.sort_unstable_by(|a, b| (b.0, &a.1.path).cmp(&(a.0, &b.1.path)));
+    folders.truncate(count);
+    folders
+}
+
+pub fn show_largest(files: &[FileRef], folders: &[(u64, FileRef)]) {
+    println!("largest files:");
+    for file in files {
+        println!("{:>18}  {}", size_as_string(file.item.size), file.path);
+    }
+    println!("largest folders:");
+    for (size, folder) in folders {
+        println!("{:>18}  {}/", size_as_string(*size), folder.path);
+    }
+}
+
+// Number and total size of some files
+fn files_as_string(sizes: impl Iterator<Item = u64>) -> String {
+    let (count, size) = sizes.fold((0, 0), |(count, total), size| (count + 1, total + size));
+    format!("{} files, {}", count, size_as_string(size))
+}
+
+pub(crate) fn show_comparison(comparison: &Comparison) {
+    println!(
+        "in both: {}",
+        files_as_string(comparison.both.iter().map(|(file, _)| file.size))
+    );
+    for (file, copies) in &comparison.both {
+        let paths = copies
+            .iter()
+            .map(|copy| copy.path.as_str())
+            .collect::<Vec<_>>();
+        println!("\t{} = {}", file.path, paths.join(", "));
+    }
+    println!(
+        "local only: {}",
+        files_as_string(comparison.local_only.iter().map(|file| file.size))
+    );
+    for file in &comparison.local_only {
+        println!("\t{}", file.path);
+    }
+    println!(
+        "drive only: {}",
+        files_as_string(comparison.drive_only.iter().map(|file| file.item.size))
+    );
+    for file in &comparison.drive_only {
+        println!("\t{}", file.path);
+    }
+}
+
+#[derive(Default, Serialize)]
+pub struct Folder<'a> {
+    pub size: u64,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub children: BTreeMap<&'a str, Folder<'a>>,
+}
+
+impl<'a> Folder<'a> {
+    // Total size of the files in the folder and its subfolders, with subfolders listed down to
+    // `depth` levels
+    fn build(state: &'a DriveState, folder: &'a Item, depth: usize) -> Folder<'a> {
+        let mut result = Folder::default();
+        for child in state.children(folder) {
+            match child.item_type {
+                ItemType::File { .. } => result.size += child.size,
+                ItemType::Folder {} | ItemType::Package {} => {
+                    let subfolder = Folder::build(state, child, depth.saturating_sub(1));
+                    if subfolder.size == 0 {
+                        continue;
+                    }
+                    result.size += subfolder.size;
+                    if depth > 0 {
+                        result.children.insert(&child.name, subfolder);
+                    }
+                }
+            }
+        }
+        result
+    }
+
+    fn show(&self, name: &str, indent: usize) {
+        println!(
+            "{:>18}  {:indent$}{}",
+            size_as_string(self.size),
+            "",
+            name,
+            indent = indent * 2
+        );
+        let mut children = self.children.iter().collect::<Vec<_>>();
+        children.sort_by_key(|(_, child)| std::cmp::Reverse(child.size));
+        for (name, child) in children {
+            child.show(name, indent + 1);
+        }
+    }
+}
+
+pub fn folder_tree(snapshot: &DriveSnapshot, depth: usize) -> Folder<'_> {
+    match snapshot.state.root() {
+        Some(root) => Folder::build(&snapshot.state, root, depth),
+        None => Folder::default(),
+    }
+}
+
+#[derive(Serialize)]
+struct FileReport {
+    id: String,
+    path: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    created: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    modified: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    created_by: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    modified_by: Option<String>,
+    // "keep" or "remove" for copies of a duplicate file
+    #[serde(skip_serializing_if = "Option::is_none")]
+    action: Option<&'static str>,
+}
+
+impl FileReport {
+    fn new(file: &FileRef) -> FileReport {
+        let item = file.item;
+        FileReport {
+            id: item.id.clone(),
+            path: file.path.clone(),
+            created: item.created_time().map(str::to_owned),
+            modified: item.modified_time().map(str::to_owned),
+            created_by: item
+                .created_by
+                .as_ref()
+                .and_then(|by| by.name())
+                .map(str::to_owned),
+            modified_by: item
+                .modified_by
+                .as_ref()
+                .and_then(|by| by.name())
+                .map(str::to_owned),
+            action: None,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct SizedFileReport {
+    id: String,
+    path: String,
+    size: u64,
+}
+
+#[derive(Serialize)]
+struct LocalFileReport {
+    path: String,
+    size: u64,
+}
+
+#[derive(Serialize)]
+struct MatchedFileReport {
+    path: String,
+    size: u64,
+    drive: Vec<FileReport>,
+}
+
+#[derive(Serialize)]
+struct ComparisonReport {
+    both: Vec<MatchedFileReport>,
+    local_only: Vec<LocalFileReport>,
+    drive_only: Vec<SizedFileReport>,
+}
+
+#[derive(Serialize)]
+struct DuplicateGroup {
+    size: u64,
+    wasted: u64,
+    hash_type: &'static str,
+    hash: String,
+    items: Vec<FileReport>,
+}
+
+#[derive(Serialize)]
+struct HashedFileReport {
+    id: String,
+    path: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sha1: Option<String>,
+    #[serde(rename = "quickXor", skip_serializing_if = "Option::is_none")]
+    quick_xor: Option<String>,
+}
+
+#[derive(Serialize)]
+struct HashMismatchGroup {
+    size: u64,
+    items: Vec<HashedFileReport>,
+}
+
+#[derive(Serialize)]
+struct ProbableDuplicateGroup {
+    size: u64,
+    items: Vec<FileReport>,
+}
+
+#[derive(Serialize)]
+struct DuplicateFolderGroup {
+    size: u64,
+    wasted: u64,
+    similarity: f64,
+    folders: Vec<FileReport>,
+}
+
+#[derive(Serialize)]
+struct FolderWaste {
+    folder: String,
+    wasted: u64,
+}
+
+#[derive(Serialize)]
+struct DriveReport {
+    id: String,
+    name: String,
+    drive_type: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    owner: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    quota: Option<Quota>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    files: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    folders: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    duplicate_folders: Option<Vec<DuplicateFolderGroup>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    duplicates: Option<Vec<DuplicateGroup>>,
+    // files without a hash, with the same size and name
+    #[serde(skip_serializing_if = "Option::is_none")]
+    probable_duplicates: Option<Vec<ProbableDuplicateGroup>>,
+    // files that share one hash but not another
+    #[serde(skip_serializing_if = "Option::is_none")]
+    hash_mismatches: Option<Vec<HashMismatchGroup>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reclaimable: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reclaimable_by_folder: Option<Vec<FolderWaste>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    largest: Option<Vec<SizedFileReport>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    largest_folders: Option<Vec<SizedFileReport>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tree: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    comparison: Option<ComparisonReport>,
+}
+
+#[derive(Serialize)]
+struct Report {
+    drives: Vec<DriveReport>,
+}
+
+// Writes each report as text immediately, or collects the reports for all drives into a single
+// JSON document written by `finish`.
+pub struct Reporter {
+    format: Format,
+    report: Report,
+}
+
+impl Reporter {
+    pub fn new(format: Format) -> Reporter {
+        Reporter {
+            format,
+            report: Report { drives: Vec::new() },
+        }
+    }
+
+    fn current(&mut self) -> &mut DriveReport {
+        self.report
+            .drives
+            .last_mut()
+            .expect("drive must be started before reporting on it")
+    }
+
+    pub fn list_drive(&mut self, drive: &Drive) {
+        match self.format {
+            Format::Text => show_drive(drive),
+            Format::Json => self.start(drive),
+        }
+    }
+
+    // Start the reports for a drive
+    pub fn drive(&mut self, drive: &Drive) {
+        match self.format {
+            Format::Text => {
+                println!();
+                println!("Drive {}", drive.id);
+            }
+            Format::Json => self.start(drive),
+        }
+    }
+
+    fn start(&mut self, drive: &Drive) {
+        self.report.drives.push(DriveReport {
+            id: drive.id.clone(),
+            name: drive.name.clone(),
+            drive_type: drive.drive_type.clone(),
+            owner: drive.owner_name().map(str::to_owned),
+            quota: None,
+            files: None,
+            folders: None,
+            duplicate_folders: None,
+            duplicates: None,
+            probable_duplicates: None,
+            hash_mismatches: None,
+            reclaimable: None,
+            reclaimable_by_folder: None,
+            largest: None,
+            largest_folders: None,
+            tree: None,
+            comparison: None,
+        });
+    }
+
+    pub fn usage(&mut self, drive: &Drive) {
+        match self.format {
+            Format::Text => match &drive.quota {
+                Some(quota) => show_usage(quota),
+                None => println!("quota:  unknown"),
+            },
+            Format::Json => {
+                self.current().quota = drive.quota.clone();
+            }
+        }
+    }
+
+    pub(crate) fn duplicates(
+        &mut self,
+        snapshot: &DriveSnapshot,
+        filter: &ItemFilter,
+        keeper: &Keeper,
+    ) {
+        let mut buckets = bucket_by_size(&snapshot.state, filter, keeper);
+        let folders = duplicate_folders(&snapshot.state, &mut buckets, keeper);
+        match self.format {
+            Format::Text => show_duplicates(&buckets, &folders),
+            Format::Json => {
+                let folder_groups = folders
+                    .groups
+                    .iter()
+                    .map(|group| DuplicateFolderGroup {
+                        size: group.size,
+                        wasted: group.wasted,
+                        similarity: group.similarity,
+                        folders: group
+                            .folders
+                            .iter()
+                            .enumerate()
+                            .map(|(copy, folder)| FileReport {
+                                action: Some(action(copy)),
+                                ..FileReport::new(folder)
+                            })
+                            .collect(),
+                    })
+                    .collect();
+                let groups = buckets
+                    .duplicates()
+                    .filter(|(_, _, files)| !folders.covers(files))
+                    .map(|(size, hash, files)| DuplicateGroup {
+                        size,
+                        wasted: wasted(size, files.len()),
+                        hash_type: hash.kind(),
+                        hash: hash.value().to_owned(),
+                        items: files
+                            .iter()
+                            .enumerate()
+                            .map(|(copy, file)| FileReport {
+                                action: Some(action(copy)),
+                                ..FileReport::new(file)
+                            })
+                            .collect(),
+                    })
+                    .collect();
+                let probable_groups = buckets
+                    .probable_duplicates()
+                    .map(|(size, files)| ProbableDuplicateGroup {
+                        size,
+                        items: files.iter().map(FileReport::new).collect(),
+                    })
+                    .collect();
+                let mismatches = buckets
+                    .mismatches
+                    .iter()
+                    .map(|(size, files)| HashMismatchGroup {
+                        size: *size,
+                        items: files
+                            .iter()
+                            .map(|file| {
+                                let mut report = HashedFileReport {
+                                    id: file.item.id.clone(),
+                                    path: file.path.clone(),
+                                    sha1: None,
+                                    quick_xor: None,
+                                };
+                                for hash in item_hashes(file.item) {
+                                    match hash {
+                                        ItemHash::Sha1(sha) => report.sha1 = Some(sha),
+                                        ItemHash::QuickXor(xor) => report.quick_xor = Some(xor),
+                                    }
+                                }
+                                report
+                            })
+                            .collect(),
+                    })
+                    .collect();
+                let reclaimable = buckets.reclaimable();
+                let report = self.current();
+                report.files = Some(buckets.file_count);
+                report.folders = Some(buckets.folder_count);
+                report.duplicate_folders = Some(folder_groups);
+                report.duplicates = Some(groups);
+                if filter.probable() {
+                    report.probable_duplicates = Some(probable_groups);
+                }
+                report.hash_mismatches = Some(mismatches);
+                report.reclaimable = Some(reclaimable.total);
+                report.reclaimable_by_folder = Some(
+                    reclaimable
+                        .by_folder
+                        .into_iter()
+                        .map(|(folder, wasted)| FolderWaste {
+                            folder: folder.to_owned(),
+                            wasted,
+                        })
+                        .collect(),
+                );
+            }
+        }
+    }
+
+    pub fn largest(
+        &mut self,
+        snapshot: &DriveSnapshot,
+        count: usize,
+        depths: &RangeInclusive<usize>,
+    ) {
+        let files = largest_files(snapshot, count, depths);
+        let folders = largest_folders(snapshot, count, depths);
+        match self.format {
+            Format::Text => show_largest(&files, &folders),
+            Format::Json => {
+                let report = self.current();
+                report.largest = Some(
+                    files
+                        .iter()
+                        .map(|file| SizedFileReport {
+                            id: file.item.id.clone(),
+                            path: file.path.clone(),
+                            size: file.item.size,
+                        })
+                        .collect(),
+                );
+                report.largest_folders = Some(
+                    folders
+                        .iter()
+                        .map(|(size, folder)| SizedFileReport {
+                            id: folder.item.id.clone(),
+                            path: folder.path.clone(),
+                            size: *size,
+                        })
+                        .collect(),
+                );
+            }
+        }
+    }
+
+    pub fn tree(&mut self, snapshot: &DriveSnapshot, depth: usize) -> Result<()> {
+        let root = folder_tree(snapshot, depth);
+        match self.format {
+            Format::Text => root.show("/", 0),
+            Format::Json => {
+                self.current().tree = Some(serde_json::to_value(&root)?);
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn comparison(&mut self, comparison: &Comparison) {
+        match self.format {
+            Format::Text => show_comparison(comparison),
+            Format::Json => {
+                self.current().comparison = Some(ComparisonReport {
+                    both: comparison
+                        .both
+                        .iter()
+                        .map(|(file, copies)| MatchedFileReport {
+                            path: file.path.clone(),
+                            size: file.size,
+                            drive: copies.iter().map(FileReport::new).collect(),
+                        })
+                        .collect(),
+                    local_only: comparison
+                        .local_only
+                        .iter()
+                        .map(|file| LocalFileReport {
+                            path: file.path.clone(),
+                            size: file.size,
+                        })
+                        .collect(),
+                    drive_only: comparison
+                        .drive_only
+                        .iter()
+                        .map(|file| SizedFileReport {
+                            id: file.item.id.clone(),
+                            path: file.path.clone(),
+                            size: file.item.size,
+                        })
+                        .collect(),
+                });
+            }
+        }
+    }
+
+    pub fn finish(self) -> Result<()> {
+        if let Format::Json = self.format {
+            println!("{}", serde_json::to_string_pretty(&self.report)?);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{largest_files, largest_folders};
+    use crate::item::DriveSnapshot;
+    use serde_json::json;
+
+    #[test]
+    fn largest_items() {
+        let mut snapshot = DriveSnapshot::default("https://graph.example.com", "DRIVE");
+        let folder = |id: &str, parent: &str| {
+            json!({
+                "id": id,
+                "name": id,
+                "parentReference": {"id": parent, "driveType": "personal"},
+                "folder": {},
+            })
+        };
+        let file = |id: &str, parent: &str, size: u64| {
+            json!({
+                "id": id,
+                "name": id,
+                "size": size,
+                "parentReference": {"id": parent, "driveType": "personal"},
+                "file": {},
+            })
+        };
+        for data in [
+            json!({"id": "ROOT", "name": "root", "parentReference": {"driveType": "personal"},
+                   "folder": {}, "root": {}}),
+            folder("A", "ROOT"),
+            folder("B", "A"),
+            folder("C", "ROOT"),
+            file("a", "A", 100),
+            file("b1", "B", 300),
+            file("b2", "B", 200),
+            file("c", "C", 550),
+            file("r", "ROOT", 50),
+        ] {
+            snapshot.state.upsert(serde_json::from_value(data).unwrap());
+        }
+        let paths = |depths| {
+            largest_files(&snapshot, 3, &depths)
+                .into_iter()
+                .map(|file| file.path)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(paths(1..=usize::MAX), ["C/c", "A/B/b1", "A/B/b2"]);
+        assert_eq!(paths(1..=2), ["C/c", "A/a", "r"]);
+        let folders = |depths| {
+            largest_folders(&snapshot, 2, &depths)
+                .into_iter()
+                .map(|(size, folder)| (size, folder.path))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            folders(1..=usize::MAX),
+            [(600, "A".to_owned()), (550, "C".to_owned())]
+        );
+        assert_eq!(folders(2..=2), [(500, "A/B".to_owned())]);
+    }
+}
@@ -1,85 +1,317 @@
+use std::cmp::Reverse;
 use std::collections::{BTreeMap, HashMap};
 
-use crate::item::{Item, ItemType};
+use crate::filter::ItemFilter;
+use crate::item::{DriveState, Item, ItemType};
+use crate::keeper::Keeper;
 
-#[derive(PartialEq, Eq, Hash)]
+#[derive(Clone, PartialEq, Eq, Hash)]
 pub(crate) enum ItemHash {
     Sha1(String),
     QuickXor(String),
 }
 
-fn ignore_path(dirname: &str, basename: &str) -> bool {
-    // SVN repo files may be duplicated in the .svn directory. Don't match these,
-    // as they are part of the SVN repo format, and should not be modified
-    // individually.
-    basename.ends_with(".svn-base") && dirname.contains("/.svn/pristine/")
+impl ItemHash {
+    pub(crate) fn kind(&self) -> &'static str {
+        match self {
+            ItemHash::Sha1(_) => "sha1",
+            ItemHash::QuickXor(_) => "quickXor",
+        }
+    }
+
+    pub(crate) fn value(&self) -> &str {
+        match self {
+            ItemHash::Sha1(value) | ItemHash::QuickXor(value) => value,
+        }
+    }
+}
+
+pub(crate) struct FileRef<'a> {
+    pub item: &'a Item,
+    pub path: String,
+}
+
+pub(crate) fn join_path(dirname: &str, basename: &str) -> String {
+    if dirname.is_empty() {
+        basename.to_owned()
+    } else {
+        format!("{}/{}", dirname, basename)
+    }
+}
+
+// The hashes Graph returns for a file. Personal drives are moving from SHA-1 to QuickXorHash, so
+// a file may have either or both.
+pub(crate) fn item_hashes(item: &Item) -> Vec<ItemHash> {
+    let mut result = Vec::new();
+    if let ItemType::File {
+        hashes: Some(hashes),
+    } = &item.item_type
+    {
+        if let Some(sha) = &hashes.sha {
+            result.push(ItemHash::Sha1(sha.to_uppercase()));
+        }
+        if let Some(xor) = &hashes.xor {
+            result.push(ItemHash::QuickXor(xor.clone()));
+        }
+    }
+    result
+}
+
+// Lower-case name without the suffixes added to copies, e.g. "Report (2).PDF" and
+// "report - Copy.pdf" both become "report.pdf"
+pub(crate) fn normalise_name(name: &str) -> String {
+    let name = name.trim().to_lowercase();
+    let (stem, extension) = match name.rsplit_once('.') {
+        Some((stem, extension)) if !stem.is_empty() => (stem, Some(extension)),
+        _ => (name.as_str(), None),
+    };
+    let mut stem = stem.trim_end();
+    loop {
+        let trimmed = stem
+            .strip_suffix(" - copy")
+            .or_else(|| stem.strip_suffix(" copy"))
+            .or_else(|| {
+                let number = stem
+                    .strip_suffix(')')?
+                    .trim_end_matches(|c: char| c.is_ascii_digit());
+                number
+                    .strip_suffix(" (")
+                    .or_else(|| number.strip_suffix('('))
+            })
+            .map(str::trim_end);
+        match trimmed {
+            Some(trimmed) if !trimmed.is_empty() => stem = trimmed,
+            _ => break,
+        }
+    }
+    match extension {
+        Some(extension) => format!("{}.{}", stem, extension),
+        None => stem.to_owned(),
+    }
+}
+
+pub(crate) type SizeBuckets<'a> = BTreeMap<u64, HashMap<ItemHash, Vec<FileRef<'a>>>>;
+
+// Files without a usable hash, by size and normalised name
+pub(crate) type ProbableBuckets<'a> = BTreeMap<(u64, String), Vec<FileRef<'a>>>;
+
+pub(crate) struct Buckets<'a> {
+    pub file_count: u32,
+    pub folder_count: u32,
+    pub by_size: SizeBuckets<'a>,
+    // only filled if the filter includes probable duplicates
+    pub probable: ProbableBuckets<'a>,
+    // copies of files that share one hash but not another, one for each version, so one of them
+    // may be corrupted
+    pub mismatches: Vec<(u64, Vec<FileRef<'a>>)>,
+    // size and hash of each group of duplicates in `by_size`, in the order they are reported
+    duplicates: Vec<(u64, ItemHash)>,
+}
+
+// Bytes freed by keeping only one of the copies of an item
+pub(crate) fn wasted(size: u64, copies: usize) -> u64 {
+    size * (copies as u64).saturating_sub(1)
+}
+
+// Name of the top-level folder containing a path, or "/" for items at the root
+fn top_folder(path: &str) -> &str {
+    match path.split_once('/') {
+        Some((top, _)) => top,
+        None => "/",
+    }
+}
+
+pub(crate) struct Reclaimable<'a> {
+    pub total: u64,
+    // bytes freed in each top-level folder, most first
+    pub by_folder: Vec<(&'a str, u64)>,
+}
+
+impl<'a> Buckets<'a> {
+    // Groups of files with the same size and hash, most wasted space first
+    pub(crate) fn duplicates(&self) -> impl Iterator<Item = (u64, &ItemHash, &[FileRef<'a>])> {
+        self.duplicates
+            .iter()
+            .map(move |(size, hash)| (*size, hash, self.by_size[size][hash].as_slice()))
+    }
+
+    // Groups of files without a hash but with the same size and name, most wasted space first.
+    // These are not included in the reclaimable space, as their contents may differ.
+    pub(crate) fn probable_duplicates(&self) -> impl Iterator<Item = (u64, &[FileRef<'a>])> {
+        let mut groups = self
+            .probable
+            .iter()
+            .rev()
+            .filter(|(_, files)| files.len() > 1)
+            .map(|((size, _), files)| (*size, files.as_slice()))
+            .collect::<Vec<_>>();
+        groups.sort_by_key(|(size, files)| Reverse(wasted(*size, files.len())));
+        groups.into_iter()
+    }
+
+    // Bytes freed by keeping only the first copy of each file, chosen by the `Keeper`
+    pub(crate) fn reclaimable(&self) -> Reclaimable<'_> {
+        let mut total = 0;
+        let mut by_folder = HashMap::<&str, u64>::new();
+        for (size, _, files) in self.duplicates() {
+            total += wasted(size, files.len());
+            for file in &files[1..] {
+                *by_folder.entry(top_folder(&file.path)).or_default() += size;
+            }
+        }
+        let mut by_folder = by_folder.into_iter().collect::<Vec<_>>();
+        by_folder.sort_by(|a, b| (b.1, a.0).cmp(&(a.1, b.0)));
+        Reclaimable { total, by_folder }
+    }
+}
+
+// Group files of the same size that share any hash. If copies disagree on a hash, the group is
+// split into versions that agree on every hash they have, and a file with only a hash that
+// several versions have belongs to none of them. Returns each version under a hash of its own,
+// and separately, for each group that disagrees, one copy of each version and the files that
+// belong to none.
+fn group_copies<'a>(
+    files: Vec<(FileRef<'a>, Vec<ItemHash>)>,
+) -> (HashMap<ItemHash, Vec<FileRef<'a>>>, Vec<Vec<FileRef<'a>>>) {
+    fn find(group: &mut [usize], mut file: usize) -> usize {
+        while group[file] != file {
+            group[file] = group[group[file]];
+            file = group[file];
+        }
+        file
+    }
+    let mut group = (0..files.len()).collect::<Vec<_>>();
+    let mut first_with = HashMap::<&ItemHash, usize>::new();
+    for (file, (_, hashes)) in files.iter().enumerate() {
+        for hash in hashes {
+            match first_with.get(hash) {
+                Some(&other) => {
+                    let root = find(&mut group, file);
+                    group[root] = find(&mut group, other);
+                }
+                None => {
+                    first_with.insert(hash, file);
+                }
+            }
+        }
+    }
+    let roots = (0..files.len())
+        .map(|file| find(&mut group, file))
+        .collect::<Vec<_>>();
+    let mut groups = HashMap::<usize, Vec<(FileRef, Vec<ItemHash>)>>::new();
+    for (root, file) in roots.into_iter().zip(files) {
+        groups.entry(root).or_default().push(file);
+    }
+    let mut files_by_hash = HashMap::new();
+    let mut mismatches = Vec::new();
+    for files in groups.into_values() {
+        // files with both hashes are the same version if both hashes match
+        let mut versions = HashMap::<Vec<ItemHash>, Vec<FileRef>>::new();
+        let (complete, partial): (Vec<_>, Vec<_>) =
+            files.into_iter().partition(|(_, hashes)| hashes.len() > 1);
+        for (file, hashes) in complete {
+            versions.entry(hashes).or_default().push(file);
+        }
+        // a file with one hash joins the only version with that hash
+        let mut conflicting = Vec::new();
+        for (file, hashes) in partial {
+            let mut matching = versions
+                .iter_mut()
+                .filter(|(version, _)| version.contains(&hashes[0]));
+            match (matching.next(), matching.next()) {
+                (Some((_, version)), None) => version.push(file),
+                (None, _) => versions.entry(hashes).or_default().push(file),
+                (Some(_), Some(_)) => conflicting.push(file),
+            }
+        }
+        let versions = versions.into_iter().collect::<Vec<_>>();
+        let keys = versions
+            .iter()
+            .map(|(hashes, _)| {
+                // prefer QuickXorHash, which every drive type is moving to
+                hashes
+                    .iter()
+                    .rev()
+                    .find(|hash| {
+                        let with_hash = versions.iter().filter(|(other, _)| other.contains(hash));
+                        with_hash.count() == 1
+                    })
+                    .cloned()
+            })
+            .collect::<Vec<_>>();
+        let disagree = versions.len() > 1;
+        for ((_, files), key) in versions.into_iter().zip(keys) {
+            match key {
+                Some(key) => {
+                    if disagree {
+                        let copy = files
+                            .iter()
+                            .min_by(|a, b| a.path.cmp(&b.path))
+                            .expect("versions have files");
+                        conflicting.push(FileRef {
+                            item: copy.item,
+                            path: copy.path.clone(),
+                        });
+                    }
+                    files_by_hash.insert(key, files);
+                }
+                // every hash of the version is shared with another version, so it cannot be
+                // listed under one
+                None => conflicting.extend(files),
+            }
+        }
+        if !conflicting.is_empty() {
+            mismatches.push(conflicting);
+        }
+    }
+    (files_by_hash, mismatches)
 }
 
-pub(crate) fn bucket_by_size(
-    names_by_hash: &HashMap<String, Item>,
-) -> (u32, u32, BTreeMap<u64, HashMap<ItemHash, Vec<String>>>) {
-    let mut names_by_hash_by_size = BTreeMap::<u64, HashMap<ItemHash, Vec<String>>>::new();
+pub(crate) fn bucket_by_size<'a>(
+    state: &'a DriveState,
+    filter: &ItemFilter,
+    keeper: &Keeper,
+) -> Buckets<'a> {
+    let mut hashed = BTreeMap::<u64, Vec<(FileRef, Vec<ItemHash>)>>::new();
+    let mut probable = ProbableBuckets::new();
     let mut file_count = 0;
     let mut folder_count = 0;
-    let bar = indicatif::ProgressBar::new(names_by_hash.len() as u64);
+    let bar = indicatif::ProgressBar::new(state.items.len() as u64);
     bar.set_style(
         indicatif::ProgressStyle::default_bar()
             .template("Analyzing duplicates: [{elapsed_precise}] {wide_bar} {percent}%")
             .progress_chars("#>-"),
     );
     bar.tick();
-    for item in names_by_hash.values() {
+    for item in state.items.values() {
         bar.inc(1);
         match &item.item_type {
-            ItemType::File { hashes } => {
+            ItemType::File { .. } => {
                 file_count += 1;
-                let dirname = match item.parent.path {
+                let dirname = match state.parent_path(item) {
                     None => {
                         // deleted parent
                         continue;
                     }
-                    Some(ref path) => path.trim_start_matches("/drive/root:/"),
+                    Some(path) => path,
                 };
-                if ignore_path(dirname, &item.name) {
+                let path = join_path(&dirname, &item.name);
+                if !filter.matches(&path, item.size) {
                     continue;
                 }
-                let hash = match hashes {
-                    Some(hashes) => match item.parent.drive_type.as_ref() {
-                        "personal" => match hashes.sha {
-                            Some(ref sha) => ItemHash::Sha1(sha.clone()),
-                            None => {
-                                eprintln!("Ignoring item due to missing sha1 hash: {:?}\n", item);
-                                continue;
-                            }
-                        },
-                        "business" | "documentLibrary" => match hashes.xor {
-                            Some(ref xor) => ItemHash::QuickXor(xor.clone()),
-                            None => {
-                                eprintln!(
-                                    "Ignoring item due to missing quickXor hash: {:?}\n",
-                                    item
-                                );
-                                continue;
-                            }
-                        },
-                        _ => {
-                            eprintln!("Ignoring item due to unknown drive_type: {:?}\n", item);
-                            continue;
-                        }
-                    },
-                    None => {
-                        // Files with the "application/msonenote" MIME Type do not have a SHA.
-                        continue;
-                    }
-                };
-                let names_by_hash = names_by_hash_by_size
-                    .entry(item.size)
-                    .or_insert_with(HashMap::<ItemHash, Vec<String>>::new);
-                // allocating the key only on insert is messy - we could use raw_entry here,
-                // or maybe entry_ref() will exist one day - for now, always allocate
-                let v = names_by_hash.entry(hash).or_insert_with(Vec::<String>::new);
-                let name = format!("{}/{}", dirname, item.name);
-                v.push(name);
+                let hashes = item_hashes(item);
+                if !hashes.is_empty() {
+                    hashed
+                        .entry(item.size)
+                        .or_default()
+                        .push((FileRef { item, path }, hashes));
+                } else if filter.probable() {
+                    probable
+                        .entry((item.size, normalise_name(&item.name)))
+                        .or_default()
+                        .push(FileRef { item, path });
+                }
+                // Files with the "application/msonenote" MIME Type do not have a SHA.
             }
             ItemType::Folder {} | ItemType::Package {} => {
                 folder_count += 1;
@@ -87,7 +319,88 @@ pub(crate) fn bucket_by_size(
         }
     }
     bar.finish_and_clear();
-    (file_count, folder_count, names_by_hash_by_size)
+    let mut names_by_hash_by_size = SizeBuckets::new();
+    let mut mismatches = Vec::new();
+    for (size, files) in hashed {
+        let (files_by_hash, mismatched) = group_copies(files);
+        names_by_hash_by_size.insert(size, files_by_hash);
+        mismatches.extend(mismatched.into_iter().map(|files| (size, files)));
+    }
+    // list the copies in a consistent order
+    for files in names_by_hash_by_size
+        .values_mut()
+        .flat_map(HashMap::values_mut)
+        .chain(probable.values_mut())
+        .chain(mismatches.iter_mut().map(|(_, files)| files))
+    {
+        files.sort_unstable_by(|a, b| a.path.cmp(&b.path));
+    }
+    // then put the copy to keep first
+    for files in names_by_hash_by_size
+        .values_mut()
+        .flat_map(HashMap::values_mut)
+    {
+        keeper.order(files);
+    }
+    mismatches.sort_by(|a, b| a.1[0].path.cmp(&b.1[0].path));
+    // most wasted space first, then larger files, then by path, so that reports are repeatable
+    let mut duplicates = names_by_hash_by_size
+        .iter()
+        .flat_map(|(size, files_by_hash)| {
+            files_by_hash
+                .iter()
+                .filter(|(_, files)| files.len() > 1)
+                .map(move |(hash, files)| {
+                    let path = files.iter().map(|file| file.path.as_str()).min();
+                    (
+                        Reverse(wasted(*size, files.len())),
+                        Reverse(*size),
+                        path,
+                        hash,
+                    )
+                })
+        })
+        .collect::<Vec<_>>();
+    duplicates.sort_unstable_by(|a, b| (a.0, a.1, a.2).cmp(&(b.0, b.1, b.2)));
+    let duplicates = duplicates
+        .into_iter()
+        .map(|(_, Reverse(size), _, hash)| (size, hash.clone()))
+        .collect();
+    Buckets {
+        file_count,
+        folder_count,
+        by_size: names_by_hash_by_size,
+        probable,
+        mismatches,
+        duplicates,
+    }
+}
+
+// Parse a size in bytes, with an optional binary suffix, e.g. 100K or 1.5G
+pub(crate) fn parse_size(value: &str) -> Result<u64, String> {
+    let value = value.trim();
+    let upper = value.to_uppercase();
+    let number = upper.trim_end_matches('B').trim_end_matches('I').trim_end();
+    let (number, scale) = match number.chars().last() {
+        Some('K') => (&number[..number.len() - 1], 1u64 << 10),
+        Some('M') => (&number[..number.len() - 1], 1 << 20),
+        Some('G') => (&number[..number.len() - 1], 1 << 30),
+        Some('T') => (&number[..number.len() - 1], 1 << 40),
+        _ => (number, 1),
+    };
+    match number.trim_end().parse::<f64>() {
+        Ok(number) if number >= 0.0 => Ok((number * scale as f64) as u64),
+        _ => Err(format!("invalid size: {}", value)),
+    }
+}
+
+// Percentage of `total` that `part` is, or 0 if the total is 0
+pub(crate) fn percent(part: u64, total: u64) -> f64 {
+    if total == 0 {
+        0.0
+    } else {
+        part as f64 * 100.0 / total as f64
+    }
 }
 
 pub(crate) fn size_as_string(value: u64) -> String {
@@ -103,3 +416,153 @@ pub(crate) fn size_as_string(value: u64) -> String {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{bucket_by_size, normalise_name, parse_size};
+    use crate::filter::ItemFilter;
+    use crate::item::DriveState;
+    use crate::keeper::Keeper;
+    use serde_json::json;
+
+    #[test]
+    fn reclaimable_space() {
+        let mut state = DriveState::default();
+        let folder = |id: &str, parent: Option<&str>| {
+            json!({
+                "id": id,
+                "name": id,
+                "parentReference": {"id": parent, "driveType": "personal"},
+                "folder": {},
+            })
+        };
+        let file = |id: &str, parent: &str, size: u64, sha: &str| {
+            json!({
+                "id": id,
+                "name": id,
+                "size": size,
+                "parentReference": {"id": parent, "driveType": "personal"},
+                "file": {"hashes": {"sha1Hash": sha}},
+            })
+        };
+        let mut root = folder("ROOT", None);
+        root["root"] = json!({});
+        for data in [
+            root,
+            folder("A", Some("ROOT")),
+            folder("B", Some("ROOT")),
+            // one large file with one extra copy
+            file("big1", "A", 1000, "BIG"),
+            file("big2", "B", 1000, "BIG"),
+            // wastes the same space, so it is ordered by path
+            file("same1", "B", 1000, "SAME"),
+            file("same2", "ROOT", 1000, "SAME"),
+            // a smaller file with more copies wastes more space
+            file("small1", "A", 600, "SMALL"),
+            file("small2", "B", 600, "SMALL"),
+            file("small3", "B", 600, "SMALL"),
+            file("small4", "ROOT", 600, "SMALL"),
+        ] {
+            state.upsert(serde_json::from_value(data).unwrap());
+        }
+        let buckets = bucket_by_size(&state, &ItemFilter::default(), &Keeper::default());
+        let hashes = buckets
+            .duplicates()
+            .map(|(_, hash, _)| hash.value())
+            .collect::<Vec<_>>();
+        assert_eq!(hashes, ["SMALL", "BIG", "SAME"]);
+        let reclaimable = buckets.reclaimable();
+        assert_eq!(reclaimable.total, 3800);
+        // the first copy, A/small1, is kept
+        assert_eq!(reclaimable.by_folder, [("B", 2200), ("/", 1600)]);
+    }
+
+    #[test]
+    fn match_across_hash_types() {
+        let mut state = DriveState::default();
+        let file = |id: &str, size: u64, hashes: serde_json::Value| {
+            json!({
+                "id": id,
+                "name": id,
+                "size": size,
+                "parentReference": {"id": "ROOT", "driveType": "personal"},
+                "file": {"hashes": hashes},
+            })
+        };
+        for data in [
+            json!({"id": "ROOT", "name": "root", "root": {}, "folder": {},
+                   "parentReference": {"driveType": "personal"}}),
+            file("sha", 500, json!({"sha1Hash": "ABC1"})),
+            file(
+                "both",
+                500,
+                json!({"sha1Hash": "abc1", "quickXorHash": "X1"}),
+            ),
+            file("xor", 500, json!({"quickXorHash": "X1"})),
+            // the same SHA-1 but a different QuickXorHash
+            file(
+                "good1",
+                700,
+                json!({"sha1Hash": "DEF2", "quickXorHash": "X2"}),
+            ),
+            file(
+                "good2",
+                700,
+                json!({"sha1Hash": "DEF2", "quickXorHash": "X2"}),
+            ),
+            file(
+                "bad",
+                700,
+                json!({"sha1Hash": "DEF2", "quickXorHash": "X3"}),
+            ),
+            // could be a copy of either
+            file("unsure", 700, json!({"sha1Hash": "DEF2"})),
+        ] {
+            state.upsert(serde_json::from_value(data).unwrap());
+        }
+        let buckets = bucket_by_size(&state, &ItemFilter::default(), &Keeper::default());
+        let groups = buckets
+            .duplicates()
+            .map(|(size, hash, files)| {
+                let paths = files.iter().map(|file| file.path.as_str()).collect();
+                (size, hash.kind(), paths)
+            })
+            .collect::<Vec<(u64, &str, Vec<&str>)>>();
+        assert_eq!(
+            groups,
+            [
+                (500, "quickXor", vec!["both", "sha", "xor"]),
+                (700, "quickXor", vec!["good1", "good2"])
+            ]
+        );
+        assert_eq!(buckets.reclaimable().total, 1700);
+        assert_eq!(buckets.mismatches.len(), 1);
+        let (size, files) = &buckets.mismatches[0];
+        assert_eq!(*size, 700);
+        let paths = files
+            .iter()
+            .map(|file| file.path.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(paths, ["bad", "good1", "unsure"]);
+    }
+
+    #[test]
+    fn normalise_names() {
+        assert_eq!(normalise_name("Report (2).PDF"), "report.pdf");
+        assert_eq!(normalise_name("report - Copy.pdf"), "report.pdf");
+        assert_eq!(normalise_name("Notes - Copy (3)"), "notes");
+        assert_eq!(normalise_name("(1).txt"), "(1).txt");
+        assert_eq!(normalise_name(".profile"), ".profile");
+        assert_eq!(normalise_name("IMG_0001.JPG"), "img_0001.jpg");
+    }
+
+    #[test]
+    fn parse_sizes() {
+        assert_eq!(parse_size("100"), Ok(100));
+        assert_eq!(parse_size("100k"), Ok(100 * 1024));
+        assert_eq!(parse_size("1.5G"), Ok(3 << 29));
+        assert_eq!(parse_size("5 MiB"), Ok(5 << 20));
+        assert!(parse_size("lots").is_err());
+        assert!(parse_size("-1").is_err());
+    }
+}
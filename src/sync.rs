@@ -1,12 +1,83 @@
-use eyre::{eyre, Result};
 use reqwest::blocking::{Client, Response};
 use reqwest::header::RETRY_AFTER;
 use reqwest::StatusCode;
 use serde_derive::Deserialize;
 use serde_json::Value;
+use std::fmt;
 use std::panic;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::mpsc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
+
+// Number of times to retry a failed page before giving up
+const MAX_RETRIES: u32 = 3;
+// Number of consecutive Retry-After responses to accept before giving up
+const MAX_THROTTLES: u32 = 10;
+// Delay before retrying a failed page
+#[cfg(not(test))]
+const RETRY_DELAY: Duration = Duration::from_secs(30);
+#[cfg(test)]
+const RETRY_DELAY: Duration = Duration::from_millis(10);
+// Interval at which a wait checks whether the sync has been stopped
+const STOP_POLL: Duration = Duration::from_millis(100);
+
+#[derive(Debug)]
+pub enum SyncError {
+    // The request could not be sent, or the response could not be received
+    Network(reqwest::Error),
+    // The response was not a valid page of drive items
+    Deserialize(serde_json::Error),
+    // The server continued to throttle requests after waiting as requested
+    ThrottlingExhausted,
+    // The access token is no longer accepted
+    AuthExpired,
+    // The sync was stopped between pages on request
+    Interrupted,
+    // The server returned an error status that did not succeed on retry
+    UnexpectedStatus {
+        status: StatusCode,
+        code: Option<String>,
+        message: Option<String>,
+    },
+}
+
+impl fmt::Display for SyncError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SyncError::Network(error) => write!(f, "Error fetching items: {}", error),
+            SyncError::Deserialize(error) => {
+                write!(f, "Could not deserialize sync page: {}", error)
+            }
+            SyncError::ThrottlingExhausted => write!(f, "Server is still throttling requests"),
+            SyncError::AuthExpired => write!(f, "Authorization has expired"),
+            SyncError::Interrupted => write!(f, "Sync was interrupted"),
+            SyncError::UnexpectedStatus {
+                status,
+                code,
+                message,
+            } => {
+                write!(f, "Unexpected response {}", status)?;
+                if let Some(code) = code {
+                    write!(f, " ({})", code)?;
+                }
+                if let Some(message) = message {
+                    write!(f, ": {}", message)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for SyncError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SyncError::Network(error) => Some(error),
+            SyncError::Deserialize(error) => Some(error),
+            _ => None,
+        }
+    }
+}
 
 pub trait DriveItemHandler<DriveItem> {
     // remove all data and start from scratch
@@ -14,9 +85,27 @@ pub trait DriveItemHandler<DriveItem> {
 
     // handle a received drive item
     fn handle(&mut self, item: DriveItem);
+
+    // all items before `next_link` have been handled, so the sync can be resumed from it
+    fn checkpoint(&mut self, _next_link: &str) {}
 }
 
-fn get(client: &Client, uri: &str) -> Result<Response> {
+// Wait for `delay`, unless the sync is stopped first
+fn sleep(delay: Duration, stop: &AtomicBool) -> Result<(), SyncError> {
+    let until = Instant::now() + delay;
+    loop {
+        if stop.load(Ordering::SeqCst) {
+            return Err(SyncError::Interrupted);
+        }
+        let now = Instant::now();
+        if now >= until {
+            return Ok(());
+        }
+        std::thread::sleep((until - now).min(STOP_POLL));
+    }
+}
+
+fn get(client: &Client, uri: &str, stop: &AtomicBool) -> Result<Response, SyncError> {
     let mut retries = 3;
     let mut delay = 1;
     loop {
@@ -28,10 +117,10 @@ fn get(client: &Client, uri: &str) -> Result<Response> {
                 eprintln!("{:?}\n", error);
             }
             Err(error) => {
-                return Err(error.into());
+                return Err(SyncError::Network(error));
             }
         }
-        std::thread::sleep(Duration::from_secs(delay));
+        sleep(Duration::from_secs(delay), stop)?;
         retries -= 1;
         delay *= 16;
     }
@@ -51,66 +140,136 @@ struct SyncPage<DriveItem> {
     link: SyncLink,
 }
 
-macro_rules! retry_or_panic {
-    ( $count:ident, $message:expr ) => {
-        if $count < 3 {
+// Sent from the fetch thread to the thread handling items
+enum SyncMessage<DriveItem> {
+    // the sync has restarted from the beginning
+    Reset,
+    // a page of items, and the link to the next page if there is one
+    Page(Vec<DriveItem>, Option<String>),
+}
+
+macro_rules! retry_or_fail {
+    ( $count:ident, $stop:expr, $error:expr ) => {
+        if $count < MAX_RETRIES {
             $count += 1;
             // extra newline to avoid overwrite by progress bar
-            eprintln!("Retry After: 30 ({})\n", $message);
-            std::thread::sleep(Duration::from_secs(30));
+            eprintln!("Retry After: {} ({})\n", RETRY_DELAY.as_secs(), $error);
+            sleep(RETRY_DELAY, $stop)?;
         } else {
-            panic!($message);
+            return Err($error);
         }
     };
 }
 
+// Print the details of an error response, and return the error code and message.
+fn error_details(response: Response) -> (Option<String>, Option<String>) {
+    let mut code = None;
+    let mut message = None;
+    match response.text() {
+        Ok(text) => {
+            eprintln!("Text: {}", text);
+            match serde_json::from_str::<Value>(&text) {
+                Ok(page) => match page.get("error") {
+                    Some(error) => {
+                        code = error.get("code").and_then(Value::as_str).map(str::to_owned);
+                        if let Some(code) = &code {
+                            eprintln!("Code: {}", code);
+                        }
+                        message = error
+                            .get("message")
+                            .and_then(Value::as_str)
+                            .filter(|message| !message.is_empty())
+                            .map(str::to_owned);
+                        if let Some(message) = &message {
+                            eprintln!("Message: {}", message);
+                        }
+                    }
+                    None => {
+                        eprintln!("Text: {:?}", text);
+                    }
+                },
+                Err(error) => {
+                    eprintln!("Text: {:?}", text);
+                    eprintln!("{}", error);
+                }
+            };
+        }
+        Err(error) => {
+            eprintln!("{}", error);
+        }
+    }
+    (code, message)
+}
+
 fn fetch_items<DriveItem>(
     client: &Client,
     reset_link: String,
     mut link: String,
-    sender: mpsc::Sender<Option<Vec<DriveItem>>>,
-) -> String
+    sender: mpsc::Sender<SyncMessage<DriveItem>>,
+    stop: &AtomicBool,
+) -> Result<String, SyncError>
 where
     DriveItem: serde::de::DeserializeOwned,
 {
     let mut fail_count = 0;
+    let mut throttle_count = 0;
+    // A 401 Unauthorized response to a saved link may mean that the link is too old. Once a
+    // page has been received with the current token, it means that the token has expired.
+    let mut restartable = link != reset_link;
     loop {
-        match get(&client, &link) {
-            Err(error) => {
+        if stop.load(Ordering::SeqCst) {
+            return Err(SyncError::Interrupted);
+        }
+        match get(client, &link, stop) {
+            Err(SyncError::Network(error)) => {
                 eprintln!("{}", error);
-                retry_or_panic!(fail_count, "Error fetching items");
+                retry_or_fail!(fail_count, stop, SyncError::Network(error));
             }
+            Err(error) => return Err(error),
             Ok(response) => match response.status() {
                 StatusCode::OK => {
                     match response.text() {
                         Ok(text) => {
                             match serde_json::from_str::<SyncPage<DriveItem>>(&text) {
                                 Ok(page) => {
-                                    sender.send(Some(page.value)).unwrap();
+                                    restartable = false;
+                                    throttle_count = 0;
                                     match page.link {
                                         SyncLink::More(next) => {
+                                            sender
+                                                .send(SyncMessage::Page(
+                                                    page.value,
+                                                    Some(next.clone()),
+                                                ))
+                                                .expect("receiver outlives the sync thread");
                                             fail_count = 0;
                                             link = next;
                                         }
                                         SyncLink::Done(delta) => {
-                                            return delta;
+                                            sender
+                                                .send(SyncMessage::Page(page.value, None))
+                                                .expect("receiver outlives the sync thread");
+                                            return Ok(delta);
                                         }
                                     }
                                 }
                                 Err(error) => {
                                     eprintln!("{}", error);
                                     eprintln!("{}", text);
-                                    retry_or_panic!(fail_count, "Could not deserialize sync page");
+                                    retry_or_fail!(fail_count, stop, SyncError::Deserialize(error));
                                 }
                             };
                         }
                         Err(error) => {
                             // error receiving full response, try again with same link
                             eprintln!("{}", error);
-                            retry_or_panic!(fail_count, "Partial response");
+                            retry_or_fail!(fail_count, stop, SyncError::Network(error));
                         }
                     }
                 }
+                StatusCode::UNAUTHORIZED if !restartable => {
+                    return Err(SyncError::AuthExpired);
+                }
                 StatusCode::GONE | StatusCode::UNAUTHORIZED => {
                     // If the server returns 410 Gone, the delta link has expired. Start a new sync
                     // using the link in the Location header:
@@ -118,8 +277,10 @@ where
                     // Although not documented, the API can return 401 Unauthorized when using an
                     // old, but correctly authorized, delta link: https://github.com/jongiddy/msod-stat/issues/1
                     eprintln!("Delta link failed, restarting sync...");
-                    // Send None to indicate that the DriveItemHandler should be reset
-                    sender.send(None).unwrap();
+                    sender
+                        .send(SyncMessage::Reset)
+                        .expect("receiver outlives the sync thread");
+                    restartable = false;
                     link = match response.headers().get("Location") {
                         Some(location) => match location.to_str() {
                             Ok(s) => s.to_owned(),
@@ -132,56 +293,38 @@ where
                     eprintln!(
                         "Response {:?} {}",
                         status,
-                        status.canonical_reason().unwrap()
+                        status.canonical_reason().unwrap_or("")
                     );
                     let retry_header = response
                         .headers()
                         .get(RETRY_AFTER)
-                        .map(|v| v.to_str().unwrap().to_string());
-                    match response.text() {
-                        Ok(text) => {
-                            eprintln!("Text: {}", text);
-                            match serde_json::from_str::<Value>(&text) {
-                                Ok(page) => match page.get("error") {
-                                    Some(error) => {
-                                        if let Some(code) =
-                                            error.get("code").and_then(Value::as_str)
-                                        {
-                                            eprintln!("Code: {}", code);
-                                        }
-                                        if let Some(message) =
-                                            error.get("message").and_then(Value::as_str)
-                                        {
-                                            if message.len() > 0 {
-                                                eprintln!("Message: {}", message);
-                                            }
-                                        }
-                                    }
-                                    None => {
-                                        eprintln!("Text: {:?}", text);
-                                    }
-                                },
-                                Err(error) => {
-                                    eprintln!("Text: {:?}", text);
-                                    eprintln!("{}", error);
-                                }
-                            };
-                        }
-                        Err(error) => {
-                            eprintln!("{}", error);
-                        }
-                    }
+                        .and_then(|v| v.to_str().ok())
+                        .map(str::to_owned);
+                    let (code, message) = error_details(response);
                     // If the server returns a Retry-After header, then everything appears OK with
                     // the request, we just need to slow down.
                     // https://docs.microsoft.com/onedrive/developer/rest-api/concepts/scan-guidance#what-happens-when-you-get-throttled
                     match retry_header {
                         Some(s) => {
+                            if throttle_count == MAX_THROTTLES {
+                                return Err(SyncError::ThrottlingExhausted);
+                            }
+                            throttle_count += 1;
                             eprintln!("Retry-After: {}\n", s);
-                            let delay = s.parse().unwrap();
-                            std::thread::sleep(Duration::from_secs(delay));
+                            // The header may also be an HTTP date, which Graph does not use
+                            let delay = s.parse().unwrap_or(30);
+                            sleep(Duration::from_secs(delay), stop)?;
                         }
                         None => {
-                            retry_or_panic!(fail_count, "Unexpected response");
+                            retry_or_fail!(
+                                fail_count,
+                                stop,
+                                SyncError::UnexpectedStatus {
+                                    status,
+                                    code,
+                                    message
+                                }
+                            );
                         }
                     }
                 }
@@ -190,47 +333,362 @@ where
     }
 }
 
-pub fn sync_drive_items<DriveItem: 'static>(
+pub fn sync_drive_items<DriveItem>(
     client: &Client,
     reset_link: String,
     link: String,
     handler: &mut impl DriveItemHandler<DriveItem>,
-) -> Result<String>
+    stop: &'static AtomicBool,
+) -> Result<String, SyncError>
 where
-    DriveItem: Send + serde::de::DeserializeOwned,
+    DriveItem: 'static + Send + serde::de::DeserializeOwned,
 {
-    let (sender, receiver) = mpsc::channel::<Option<Vec<DriveItem>>>();
+    let (sender, receiver) = mpsc::channel::<SyncMessage<DriveItem>>();
     let client = client.clone();
-    let t = std::thread::spawn(move || fetch_items(&client, reset_link, link, sender));
+    let t = std::thread::spawn(move || fetch_items(&client, reset_link, link, sender, stop));
     loop {
         match receiver.recv() {
-            Ok(Some(items)) => {
+            Ok(SyncMessage::Page(items, next_link)) => {
                 for item in items.into_iter() {
                     handler.handle(item);
                 }
+                if let Some(next_link) = next_link {
+                    handler.checkpoint(&next_link);
+                }
             }
-            Ok(None) => {
-                // None indicates that the sender thread has had to restart the sync from the beginning.
+            Ok(SyncMessage::Reset) => {
                 handler.reset();
             }
             Err(mpsc::RecvError) => {
                 // RecvError means that the sender has closed the channel. This only happens
-                // when there are no more pages or the sending thread has panicked.
+                // when there are no more pages or the sending thread has finished with an error.
                 break;
             }
         }
     }
     match t.join() {
-        Ok(delta_link) => Ok(delta_link),
-        Err(err) => {
-            match err.downcast::<&str>() {
-                Ok(s) => {
-                    Err(eyre!(s))
-                }
-                Err(err) => {
-                    panic::resume_unwind(err)
+        Ok(result) => result,
+        Err(err) => panic::resume_unwind(err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{sync_drive_items, DriveItemHandler, SyncError};
+    use crate::fake_graph::{FakeGraph, FakeResponse};
+    use crate::item::{initial_link, DriveState, Item};
+    use reqwest::blocking::Client;
+    use reqwest::StatusCode;
+    use serde_json::{json, Value};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::time::{Duration, Instant};
+
+    static RUN: AtomicBool = AtomicBool::new(false);
+    static STOP: AtomicBool = AtomicBool::new(true);
+
+    fn file(id: &str, size: u64) -> Value {
+        json!({
+            "id": id,
+            "name": format!("{}.txt", id),
+            "size": size,
+            "parentReference": {
+                "id": "ROOT",
+                "driveType": "personal"
+            },
+            "file": {
+                "hashes": {
+                    "sha1Hash": id
                 }
             }
+        })
+    }
+
+    fn deleted(id: &str) -> Value {
+        json!({
+            "id": id,
+            "name": format!("{}.txt", id),
+            "parentReference": {
+                "driveType": "personal"
+            },
+            "file": {},
+            "deleted": {}
+        })
+    }
+
+    fn state() -> DriveState {
+        DriveState::default()
+    }
+
+    // Sync from `link` (relative to the server) and return the result and requested paths.
+    fn sync(
+        script: Vec<FakeResponse>,
+        link: Option<&str>,
+        state: &mut DriveState,
+    ) -> (Result<String, SyncError>, Vec<String>) {
+        let server = FakeGraph::start(script);
+        let reset_link = initial_link(server.url(), "DRIVE");
+        let link = match link {
+            Some(link) => format!("{}{}", server.url(), link),
+            None => reset_link.clone(),
+        };
+        let result = sync_drive_items::<Item>(&Client::new(), reset_link, link, state, &RUN);
+        let requested = server.finish();
+        (result, requested)
+    }
+
+    #[test]
+    fn initial_sync() {
+        let mut state = state();
+        let (result, requested) = sync(
+            vec![
+                FakeResponse::next_page(json!([file("A", 1), file("B", 2)]), "/page2"),
+                FakeResponse::last_page(json!([file("C", 4)]), "/delta1"),
+            ],
+            None,
+            &mut state,
+        );
+        assert!(result.unwrap().ends_with("/delta1"));
+        assert_eq!(requested.len(), 2);
+        assert!(requested[0].starts_with("/me/drives/DRIVE/root/delta?select="));
+        assert_eq!(requested[1], "/page2");
+        assert_eq!(state.items.len(), 3);
+        assert_eq!(state.size, 7);
+    }
+
+    #[test]
+    fn delta_sync() {
+        let mut state = state();
+        let (result, _) = sync(
+            vec![FakeResponse::last_page(
+                json!([file("A", 1), file("B", 2)]),
+                "/delta1",
+            )],
+            None,
+            &mut state,
+        );
+        result.unwrap();
+        let (result, requested) = sync(
+            vec![FakeResponse::last_page(
+                json!([deleted("A"), file("B", 8)]),
+                "/delta2",
+            )],
+            Some("/delta1"),
+            &mut state,
+        );
+        assert!(result.unwrap().ends_with("/delta2"));
+        assert_eq!(requested, ["/delta1"]);
+        assert_eq!(state.items.len(), 1);
+        assert_eq!(state.size, 8);
+    }
+
+    // Records the links passed to `checkpoint`
+    struct Checkpoints {
+        state: DriveState,
+        links: Vec<String>,
+    }
+
+    impl DriveItemHandler<Item> for Checkpoints {
+        fn reset(&mut self) {
+            DriveItemHandler::reset(&mut self.state);
+            self.links.clear();
+        }
+
+        fn handle(&mut self, item: Item) {
+            self.state.handle(item);
+        }
+
+        fn checkpoint(&mut self, next_link: &str) {
+            self.links.push(next_link.to_owned());
         }
     }
+
+    #[test]
+    fn checkpoint_after_each_page() {
+        let server = FakeGraph::start(vec![
+            FakeResponse::next_page(json!([file("A", 1)]), "/page2"),
+            FakeResponse::next_page(json!([file("B", 2)]), "/page3"),
+            FakeResponse::last_page(json!([file("C", 4)]), "/delta1"),
+        ]);
+        let reset_link = initial_link(server.url(), "DRIVE");
+        let mut handler = Checkpoints {
+            state: state(),
+            links: Vec::new(),
+        };
+        let result = sync_drive_items(
+            &Client::new(),
+            reset_link.clone(),
+            reset_link,
+            &mut handler,
+            &RUN,
+        );
+        let base = server.url().to_owned();
+        server.finish();
+        assert!(result.unwrap().ends_with("/delta1"));
+        assert_eq!(
+            handler.links,
+            [format!("{}/page2", base), format!("{}/page3", base)]
+        );
+    }
+
+    #[test]
+    fn expired_next_link_restarts_sync() {
+        // a partial initial sync saved with the link to its third page
+        let mut state = state();
+        state.upsert(serde_json::from_value(file("A", 1)).unwrap());
+        state.upsert(serde_json::from_value(file("B", 2)).unwrap());
+        let (result, requested) = sync(
+            vec![
+                FakeResponse::gone("/restart"),
+                FakeResponse::last_page(json!([file("A", 1)]), "/delta1"),
+            ],
+            Some("/page3"),
+            &mut state,
+        );
+        assert!(result.unwrap().ends_with("/delta1"));
+        assert_eq!(requested, ["/page3", "/restart"]);
+        assert_eq!(state.items.len(), 1);
+        assert_eq!(state.size, 1);
+    }
+
+    #[test]
+    fn gone_restarts_sync() {
+        let mut state = state();
+        state.upsert(serde_json::from_value(file("OLD", 16)).unwrap());
+        let (result, requested) = sync(
+            vec![
+                FakeResponse::gone("/restart"),
+                FakeResponse::last_page(json!([file("A", 1)]), "/delta2"),
+            ],
+            Some("/delta1"),
+            &mut state,
+        );
+        assert!(result.unwrap().ends_with("/delta2"));
+        assert_eq!(requested, ["/delta1", "/restart"]);
+        assert!(!state.items.contains_key("OLD"));
+        assert_eq!(state.size, 1);
+    }
+
+    #[test]
+    fn unauthorized_saved_link_restarts_sync() {
+        let mut state = state();
+        let (result, requested) = sync(
+            vec![
+                FakeResponse::error(401, "unauthenticated"),
+                FakeResponse::last_page(json!([file("A", 1)]), "/delta2"),
+            ],
+            Some("/delta1"),
+            &mut state,
+        );
+        assert!(result.unwrap().ends_with("/delta2"));
+        assert_eq!(requested.len(), 2);
+        assert!(requested[1].starts_with("/me/drives/DRIVE/root/delta?select="));
+    }
+
+    #[test]
+    fn unauthorized_fresh_link_fails() {
+        let mut state = state();
+        let (result, _) = sync(
+            vec![FakeResponse::error(401, "unauthenticated")],
+            None,
+            &mut state,
+        );
+        assert!(matches!(result, Err(SyncError::AuthExpired)));
+    }
+
+    #[test]
+    fn throttled_then_succeeds() {
+        let mut state = state();
+        let (result, requested) = sync(
+            vec![
+                FakeResponse::throttled(0),
+                FakeResponse::throttled(0),
+                FakeResponse::last_page(json!([file("A", 1)]), "/delta1"),
+            ],
+            None,
+            &mut state,
+        );
+        assert!(result.unwrap().ends_with("/delta1"));
+        assert_eq!(requested.len(), 3);
+        assert_eq!(state.items.len(), 1);
+    }
+
+    #[test]
+    fn throttling_exhausted() {
+        let mut state = state();
+        let script = (0..=super::MAX_THROTTLES)
+            .map(|_| FakeResponse::throttled(0))
+            .collect();
+        let (result, _) = sync(script, None, &mut state);
+        assert!(matches!(result, Err(SyncError::ThrottlingExhausted)));
+    }
+
+    #[test]
+    fn malformed_page_is_retried() {
+        let mut state = state();
+        let (result, requested) = sync(
+            vec![
+                FakeResponse::malformed(),
+                FakeResponse::last_page(json!([file("A", 1)]), "/delta1"),
+            ],
+            None,
+            &mut state,
+        );
+        assert!(result.unwrap().ends_with("/delta1"));
+        assert_eq!(requested.len(), 2);
+        assert_eq!(requested[0], requested[1]);
+    }
+
+    #[test]
+    fn malformed_page_fails() {
+        let mut state = state();
+        let script = (0..=super::MAX_RETRIES)
+            .map(|_| FakeResponse::malformed())
+            .collect();
+        let (result, _) = sync(script, None, &mut state);
+        assert!(matches!(result, Err(SyncError::Deserialize(_))));
+    }
+
+    #[test]
+    fn server_error_fails() {
+        let mut state = state();
+        let script = (0..=super::MAX_RETRIES)
+            .map(|_| FakeResponse::error(500, "generalException"))
+            .collect();
+        let (result, _) = sync(script, None, &mut state);
+        match result {
+            Err(SyncError::UnexpectedStatus { status, code, .. }) => {
+                assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+                assert_eq!(code.as_deref(), Some("generalException"));
+            }
+            _ => panic!("expected UnexpectedStatus"),
+        }
+    }
+
+    #[test]
+    fn interrupted_before_next_page() {
+        let server = FakeGraph::start(vec![FakeResponse::last_page(json!([]), "/delta1")]);
+        let link = initial_link(server.url(), "DRIVE");
+        let result =
+            sync_drive_items::<Item>(&Client::new(), link.clone(), link, &mut state(), &STOP);
+        assert!(server.finish().is_empty());
+        assert!(matches!(result, Err(SyncError::Interrupted)));
+    }
+
+    #[test]
+    fn interrupted_while_throttled() {
+        static LATER: AtomicBool = AtomicBool::new(false);
+        let server = FakeGraph::start(vec![FakeResponse::throttled(3600)]);
+        let link = initial_link(server.url(), "DRIVE");
+        let start = Instant::now();
+        let stopper = std::thread::spawn(|| {
+            std::thread::sleep(Duration::from_millis(200));
+            LATER.store(true, Ordering::SeqCst);
+        });
+        let result =
+            sync_drive_items::<Item>(&Client::new(), link.clone(), link, &mut state(), &LATER);
+        stopper.join().unwrap();
+        assert_eq!(server.finish().len(), 1);
+        assert!(matches!(result, Err(SyncError::Interrupted)));
+        assert!(start.elapsed() < Duration::from_secs(60));
+    }
 }